@@ -0,0 +1,387 @@
+use std::fs::read_to_string;
+
+use log::{error, info, warn};
+use pam_sys::PamReturnCode;
+use serde::Deserialize;
+
+use crate::auth::{
+    AuthBackend, AuthConversation, AuthUserInfo, AuthenticationError, BackendSession, SessionHints,
+};
+
+/// The users that are used when no file for the mock backend is given
+const DEFAULT_MOCK_USERS: &str = include_str!("../../extra/mock_users.toml");
+
+/// A way in which the mock backend is forced to fail
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum MockFailure {
+    #[serde(rename = "service")]
+    Service,
+    #[serde(rename = "invalid-credentials")]
+    InvalidCredentials,
+    #[serde(rename = "max-tries")]
+    MaxTries,
+    #[serde(rename = "account-expired")]
+    AccountExpired,
+    #[serde(rename = "account-denied")]
+    AccountDenied,
+    #[serde(rename = "password-expired")]
+    PasswordExpired,
+    #[serde(rename = "credentials")]
+    Credentials,
+    #[serde(rename = "session")]
+    Session,
+}
+
+/// A user known to the mock backend
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockUser {
+    pub username: String,
+    pub password: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    #[serde(default)]
+    pub groups: Vec<libc::gid_t>,
+    pub home: String,
+    pub shell: String,
+
+    /// An additional value that is asked with a prompt (e.g. a one-time password)
+    #[serde(default)]
+    pub prompt_answer: Option<String>,
+
+    /// Make every login of this user fail in a specific way
+    #[serde(default)]
+    pub failure: Option<MockFailure>,
+}
+
+/// The file that drives the mock backend
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockUsers {
+    /// Make every login fail in a specific way
+    #[serde(default)]
+    pub failure: Option<MockFailure>,
+
+    #[serde(default)]
+    pub users: Vec<MockUser>,
+}
+
+/// An authentication backend that checks against a list of users from a file, without any PAM
+/// stack or root privileges. This is meant for previewing and testing.
+pub struct MockBackend {
+    users: MockUsers,
+}
+
+impl MockBackend {
+    pub fn new(users: MockUsers) -> Self {
+        Self { users }
+    }
+
+    /// Load the users from a file. An empty path loads the default users, but only in preview
+    /// mode. Otherwise, a file that cannot be loaded leaves the backend without users, so every
+    /// login fails.
+    pub fn from_file(path: &str, preview: bool) -> Self {
+        if path.is_empty() {
+            if preview {
+                return Self::new(
+                    toml::from_str(DEFAULT_MOCK_USERS).expect("Default mock users are valid"),
+                );
+            }
+
+            error!("No mock users file given. Refusing all logins");
+            return Self::new(MockUsers::default());
+        }
+
+        let users = read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| toml::from_str(&contents).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| {
+                if preview {
+                    warn!("Failed to load mock users from '{path}'. Reason: {err}");
+                } else {
+                    error!("Failed to load mock users from '{path}'. Refusing all logins. Reason: {err}");
+                }
+                MockUsers::default()
+            });
+
+        Self::new(users)
+    }
+}
+
+impl MockFailure {
+    fn into_error(self) -> AuthenticationError {
+        use AuthenticationError as E;
+        use PamReturnCode as C;
+
+        match self {
+            Self::Service => E::PamService("mock".to_string(), C::SYSTEM_ERR),
+            Self::InvalidCredentials => E::Authentication(C::AUTH_ERR),
+            Self::MaxTries => E::Authentication(C::MAXTRIES),
+            Self::AccountExpired => E::AccountValidation(C::ACCT_EXPIRED),
+            Self::AccountDenied => E::AccountValidation(C::PERM_DENIED),
+            Self::PasswordExpired => E::PasswordChange(C::AUTHTOK_ERR),
+            Self::Credentials => E::Credentials(C::CRED_ERR),
+            Self::Session => E::SessionOpen(C::SESSION_ERR),
+        }
+    }
+}
+
+impl AuthBackend for MockBackend {
+    fn open_session(
+        &self,
+        username: &str,
+        password: &str,
+        _hints: &SessionHints,
+        mut conversation: Box<dyn AuthConversation>,
+    ) -> Result<AuthUserInfo, AuthenticationError> {
+        use MockFailure as F;
+
+        let failure = self.users.failure;
+        if failure == Some(F::Service) {
+            return Err(F::Service.into_error());
+        }
+
+        let user = self
+            .users
+            .users
+            .iter()
+            .find(|user| user.username == username)
+            .ok_or(AuthenticationError::Authentication(
+                PamReturnCode::USER_UNKNOWN,
+            ))?;
+
+        if user.password != password {
+            return Err(F::InvalidCredentials.into_error());
+        }
+
+        if let Some(expected) = &user.prompt_answer {
+            let answer = conversation
+                .prompt("Verification code: ", true)
+                .ok_or(AuthenticationError::Authentication(PamReturnCode::CONV_ERR))?;
+
+            if &answer != expected {
+                return Err(F::InvalidCredentials.into_error());
+            }
+        }
+
+        match failure.or(user.failure) {
+            None => {}
+            Some(F::PasswordExpired) => {
+                info!("Password of mock user '{username}' expired. Requesting a new password");
+
+                let change = conversation
+                    .change_password()
+                    .ok_or(AuthenticationError::PasswordChangeAborted)?;
+
+                if change.current != user.password || change.new.is_empty() {
+                    return Err(F::PasswordExpired.into_error());
+                }
+
+                conversation.info("Password changed");
+            }
+            Some(failure) => return Err(failure.into_error()),
+        }
+
+        info!("Authenticated mock user '{username}'");
+
+        let mut all_gids = user.groups.clone();
+        if !all_gids.contains(&user.gid) {
+            all_gids.insert(0, user.gid);
+        }
+
+        Ok(AuthUserInfo {
+            session: BackendSession::Mock,
+
+            username: username.to_string(),
+            uid: user.uid,
+            primary_gid: user.gid,
+            all_gids,
+            home_dir: user.home.clone(),
+            shell: user.shell.clone(),
+            environment: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::PasswordChange;
+
+    struct TestConversation {
+        prompt_answer: Option<String>,
+        password_change: Option<(String, String)>,
+    }
+
+    impl AuthConversation for TestConversation {
+        fn prompt(&mut self, _message: &str, _echo: bool) -> Option<String> {
+            self.prompt_answer.clone()
+        }
+        fn info(&mut self, _message: &str) {}
+        fn error(&mut self, _message: &str) {}
+        fn change_password(&mut self) -> Option<PasswordChange> {
+            self.password_change
+                .clone()
+                .map(|(current, new)| PasswordChange { current, new })
+        }
+    }
+
+    fn conversation() -> Box<dyn AuthConversation> {
+        Box::new(TestConversation {
+            prompt_answer: None,
+            password_change: None,
+        })
+    }
+
+    fn hints() -> SessionHints {
+        SessionHints {
+            tty: 2,
+            seat: "seat0".to_string(),
+            session_type: "tty",
+            session_class: "user",
+            display: None,
+        }
+    }
+
+    fn backend(contents: &str) -> MockBackend {
+        MockBackend::new(toml::from_str(contents).unwrap())
+    }
+
+    const USERS: &str = r#"
+        [[users]]
+        username = "alice"
+        password = "wonderland"
+        uid = 1000
+        gid = 1000
+        groups = [10, 100]
+        home = "/home/alice"
+        shell = "/bin/bash"
+
+        [[users]]
+        username = "bob"
+        password = "builder"
+        uid = 1001
+        gid = 1001
+        home = "/home/bob"
+        shell = "/bin/zsh"
+        failure = "account-expired"
+
+        [[users]]
+        username = "carol"
+        password = "singer"
+        uid = 1002
+        gid = 1002
+        home = "/home/carol"
+        shell = "/bin/sh"
+        prompt_answer = "123456"
+    "#;
+
+    #[test]
+    fn default_users_parse() {
+        let users: MockUsers = toml::from_str(DEFAULT_MOCK_USERS).unwrap();
+        assert!(!users.users.is_empty());
+    }
+
+    #[test]
+    fn successful_login() {
+        let info = backend(USERS)
+            .open_session("alice", "wonderland", &hints(), conversation())
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        assert_eq!(info.username, "alice");
+        assert_eq!(info.uid, 1000);
+        assert_eq!(info.primary_gid, 1000);
+        assert_eq!(info.all_gids, vec![1000, 10, 100]);
+        assert_eq!(info.home_dir, "/home/alice");
+        assert_eq!(info.shell, "/bin/bash");
+    }
+
+    #[test]
+    fn failed_logins() {
+        let backend = backend(USERS);
+
+        assert!(matches!(
+            backend.open_session("alice", "looking-glass", &hints(), conversation()),
+            Err(AuthenticationError::Authentication(PamReturnCode::AUTH_ERR))
+        ));
+        assert!(matches!(
+            backend.open_session("dave", "anything", &hints(), conversation()),
+            Err(AuthenticationError::Authentication(
+                PamReturnCode::USER_UNKNOWN
+            ))
+        ));
+        assert!(matches!(
+            backend.open_session("bob", "builder", &hints(), conversation()),
+            Err(AuthenticationError::AccountValidation(
+                PamReturnCode::ACCT_EXPIRED
+            ))
+        ));
+    }
+
+    #[test]
+    fn global_failure() {
+        let contents = format!("failure = \"session\"\n{USERS}");
+
+        assert!(matches!(
+            backend(&contents).open_session("alice", "wonderland", &hints(), conversation()),
+            Err(AuthenticationError::SessionOpen(PamReturnCode::SESSION_ERR))
+        ));
+    }
+
+    #[test]
+    fn prompts() {
+        let backend = backend(USERS);
+
+        let answer = |answer: Option<&str>| {
+            Box::new(TestConversation {
+                prompt_answer: answer.map(str::to_string),
+                password_change: None,
+            })
+        };
+
+        assert!(backend
+            .open_session("carol", "singer", &hints(), answer(Some("123456")))
+            .is_ok());
+        assert!(matches!(
+            backend.open_session("carol", "singer", &hints(), answer(Some("654321"))),
+            Err(AuthenticationError::Authentication(PamReturnCode::AUTH_ERR))
+        ));
+        assert!(matches!(
+            backend.open_session("carol", "singer", &hints(), answer(None)),
+            Err(AuthenticationError::Authentication(PamReturnCode::CONV_ERR))
+        ));
+    }
+
+    #[test]
+    fn password_change() {
+        let contents = format!("failure = \"password-expired\"\n{USERS}");
+        let backend = backend(&contents);
+
+        let change = |change: Option<(&str, &str)>| {
+            Box::new(TestConversation {
+                prompt_answer: None,
+                password_change: change.map(|(c, n)| (c.to_string(), n.to_string())),
+            })
+        };
+
+        assert!(backend
+            .open_session(
+                "alice",
+                "wonderland",
+                &hints(),
+                change(Some(("wonderland", "rabbit")))
+            )
+            .is_ok());
+        assert!(matches!(
+            backend.open_session(
+                "alice",
+                "wonderland",
+                &hints(),
+                change(Some(("wrong", "rabbit")))
+            ),
+            Err(AuthenticationError::PasswordChange(_))
+        ));
+        assert!(matches!(
+            backend.open_session("alice", "wonderland", &hints(), change(None)),
+            Err(AuthenticationError::PasswordChangeAborted)
+        ));
+    }
+}
@@ -1,17 +1,112 @@
+mod mock;
 mod pam;
 pub mod utmpx;
 
-use ::pam::{Authenticator, PasswordConv};
 use log::info;
 
-use crate::auth::pam::open_session;
 pub use crate::auth::pam::AuthenticationError;
+use crate::auth::{mock::MockBackend, pam::PamBackend, pam::PamSession};
+use crate::config::{AuthBackendKind, Config};
+use crate::post_login::PostLoginEnvironment;
 
-pub struct AuthUserInfo<'a> {
+/// The conversation that the authentication modules have with the user that is logging in.
+///
+/// Next to the username and password of the login form, modules may ask for additional
+/// information (e.g. a one-time password) or show messages to the user.
+pub trait AuthConversation {
+    /// Ask the user for a value. `echo` indicates whether the typed characters may be shown.
+    ///
+    /// Returns `None` if the user aborted the prompt.
+    fn prompt(&mut self, message: &str, echo: bool) -> Option<String>;
+
+    /// Show an informational message to the user
+    fn info(&mut self, message: &str);
+
+    /// Show an error message to the user
+    fn error(&mut self, message: &str);
+
+    /// Ask the user to choose a new password, because their current password expired.
+    ///
+    /// Returns `None` if the user aborted the password change.
+    fn change_password(&mut self) -> Option<PasswordChange>;
+}
+
+/// A conversation that aborts every prompt, for tests
+#[cfg(test)]
+pub struct NoConversation;
+
+#[cfg(test)]
+impl AuthConversation for NoConversation {
+    fn prompt(&mut self, _message: &str, _echo: bool) -> Option<String> {
+        None
+    }
+    fn info(&mut self, _message: &str) {}
+    fn error(&mut self, _message: &str) {}
+    fn change_password(&mut self) -> Option<PasswordChange> {
+        None
+    }
+}
+
+/// A new password chosen by the user to replace their expired password
+pub struct PasswordChange {
+    pub current: String,
+    pub new: String,
+}
+
+/// Information about the session that is about to be opened. Backends use this to register the
+/// session (e.g. with logind through `pam_systemd`).
+pub struct SessionHints {
+    pub tty: u8,
+    pub seat: String,
+    pub session_type: &'static str,
+    pub session_class: &'static str,
+    /// The X display, if the session runs on X11
+    pub display: Option<String>,
+}
+
+impl SessionHints {
+    /// `display` is the X display that the session uses, if it runs on X11
+    pub fn new(
+        config: &Config,
+        post_login_env: &PostLoginEnvironment,
+        display: Option<String>,
+    ) -> Self {
+        Self {
+            tty: config.tty,
+            seat: config.seat.clone(),
+            session_type: post_login_env.to_xdg_type(),
+            session_class: "user",
+            display,
+        }
+    }
+}
+
+/// A way to authenticate users and open their sessions
+pub trait AuthBackend {
+    /// Authenticate the user and open a session for them. The session stays open as long as the
+    /// returned [`AuthUserInfo`] is alive.
+    fn open_session(
+        &self,
+        username: &str,
+        password: &str,
+        hints: &SessionHints,
+        conversation: Box<dyn AuthConversation>,
+    ) -> Result<AuthUserInfo, AuthenticationError>;
+}
+
+/// The session that a backend keeps open for an authenticated user
+enum BackendSession {
+    // Only held to keep the PAM transaction alive until it is dropped.
+    #[allow(dead_code)]
+    Pam(PamSession),
+    Mock,
+}
+
+pub struct AuthUserInfo {
     // This is used to keep the user session. If the struct is dropped then the user session is
     // also automatically dropped.
     #[allow(dead_code)]
-    authenticator: Authenticator<'a, PasswordConv>,
+    session: BackendSession,
 
     #[allow(dead_code)]
     pub username: String,
@@ -21,20 +116,86 @@ pub struct AuthUserInfo<'a> {
     pub all_gids: Vec<libc::gid_t>,
     pub home_dir: String,
     pub shell: String,
+
+    /// The environment variables that the backend sets for the session
+    pub environment: Vec<(String, String)>,
+}
+
+/// Create the authentication backend that is selected in the configuration. The built-in users of
+/// the mock backend are only available in preview mode.
+pub fn get_backend(config: &Config, preview: bool) -> Box<dyn AuthBackend> {
+    match config.auth_backend {
+        AuthBackendKind::Pam => Box::new(PamBackend::new(&config.pam_service)),
+        AuthBackendKind::Mock => Box::new(MockBackend::from_file(&config.mock_users_path, preview)),
+    }
 }
 
-pub fn try_auth<'a>(
+pub fn try_auth(
     username: &str,
     password: &str,
-    pam_service: &str,
-) -> Result<AuthUserInfo<'a>, AuthenticationError> {
+    config: &Config,
+    hints: &SessionHints,
+    conversation: Box<dyn AuthConversation>,
+    preview: bool,
+) -> Result<AuthUserInfo, AuthenticationError> {
     info!("Login attempt for '{username}'");
 
-    open_session(username, password, pam_service).inspect_err(|err| {
-        info!(
-            "Authentication failed for '{}'. Reason: {}",
+    let backend = get_backend(config, preview);
+    backend
+        .open_session(username, password, hints, conversation)
+        .inspect_err(|err| {
+            info!(
+                "Authentication failed for '{}'. Reason: {}",
+                username,
+                err.to_string()
+            );
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthBackendKind;
+
+    fn login(
+        username: &str,
+        password: &str,
+        preview: bool,
+    ) -> Result<AuthUserInfo, AuthenticationError> {
+        let config = Config {
+            auth_backend: AuthBackendKind::Mock,
+            ..Default::default()
+        };
+
+        let hints = SessionHints::new(&config, &PostLoginEnvironment::Shell, None);
+        try_auth(
             username,
-            err.to_string()
-        );
-    })
+            password,
+            &config,
+            &hints,
+            Box::new(NoConversation),
+            preview,
+        )
+    }
+
+    #[test]
+    fn mock_backend_in_preview() {
+        let info = login("demo", "demo", true).unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(info.username, "demo");
+        assert_eq!(info.uid, 1000);
+
+        assert!(matches!(
+            login("demo", "wrong", true),
+            Err(AuthenticationError::Authentication(_))
+        ));
+    }
+
+    #[test]
+    fn mock_backend_without_preview() {
+        // The built-in demo users must not be able to log in outside of preview mode
+        assert!(matches!(
+            login("demo", "demo", false),
+            Err(AuthenticationError::Authentication(_))
+        ));
+    }
 }
@@ -1,60 +1,523 @@
+use std::ffi::{CStr, CString};
 use std::fmt;
+use std::ptr;
 
-use log::info;
+use libc::{c_int, c_void};
+use log::{info, warn};
 
-use pam::Authenticator;
+use pam_sys::{
+    PamConversation, PamFlag, PamHandle, PamItemType, PamMessage, PamMessageStyle, PamResponse,
+    PamReturnCode,
+};
 use uzers::os::unix::UserExt;
 
-use crate::auth::AuthUserInfo;
+use crate::auth::{
+    AuthBackend, AuthConversation, AuthUserInfo, BackendSession, PasswordChange, SessionHints,
+};
+use crate::config::AuthenticationMessagesConfig;
 
 /// All the different errors that can occur during PAM opening an authenticated session
+///
+/// Errors coming from PAM keep the return code of the failed call.
 #[derive(Clone)]
 pub enum AuthenticationError {
-    PamService(String),
-    AccountValidation,
+    PamService(String, PamReturnCode),
+    Authentication(PamReturnCode),
+    AccountValidation(PamReturnCode),
     HomeDirInvalidUtf8,
     ShellInvalidUtf8,
     UsernameNotFound,
-    SessionOpen,
+    Credentials(PamReturnCode),
+    SessionOpen(PamReturnCode),
+    PasswordChangeAborted,
+    PasswordChange(PamReturnCode),
 }
 
 impl fmt::Display for AuthenticationError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::PamService(service) => write!(f, "Failed to create authenticator with PAM service '{service}'"),
-            Self::AccountValidation => f.write_str("Invalid login credentials"),
+            Self::PamService(service, code) => write!(f, "Failed to create authenticator with PAM service '{service}'. Code: {code}"),
+            Self::Authentication(code) => write!(f, "Invalid login credentials. Code: {code}"),
+            Self::AccountValidation(code) => write!(f, "Account validation failed. Code: {code}"),
             Self::HomeDirInvalidUtf8 => f.write_str("User home directory path contains invalid UTF-8"),
             Self::ShellInvalidUtf8 => f.write_str("User shell path contains invalid UTF-8"),
             Self::UsernameNotFound => f.write_str("Login creditionals are valid, but username is not found. This should not be possible :("),
-            Self::SessionOpen => f.write_str("Failed to open a PAM session"),
+            Self::Credentials(code) => write!(f, "Failed to establish the user credentials. Code: {code}"),
+            Self::SessionOpen(code) => write!(f, "Failed to open a PAM session. Code: {code}"),
+            Self::PasswordChangeAborted => f.write_str("Password expired and changing it was aborted"),
+            Self::PasswordChange(code) => write!(f, "Failed to change the expired password. Code: {code}"),
         }
     }
 }
 
-/// Open a PAM authenticated session
-pub fn open_session<'a>(
+impl AuthenticationError {
+    /// Whether the error is a rejected login attempt, as opposed to a failure of the system
+    pub fn is_failed_login(&self) -> bool {
+        matches!(
+            self,
+            Self::Authentication(_)
+                | Self::AccountValidation(_)
+                | Self::PasswordChangeAborted
+                | Self::PasswordChange(_)
+        )
+    }
+
+    /// The message that is shown to the user for this error
+    pub fn user_message<'a>(&self, messages: &'a AuthenticationMessagesConfig) -> &'a str {
+        use PamReturnCode as C;
+
+        match self {
+            Self::PamService(..) => &messages.service_error,
+            Self::Authentication(C::USER_UNKNOWN) | Self::AccountValidation(C::USER_UNKNOWN) => {
+                &messages.user_unknown
+            }
+            Self::Authentication(C::MAXTRIES) | Self::AccountValidation(C::MAXTRIES) => {
+                &messages.max_tries
+            }
+            Self::Authentication(_) => &messages.invalid_credentials,
+            Self::AccountValidation(C::ACCT_EXPIRED) => &messages.account_expired,
+            Self::AccountValidation(_) => &messages.account_denied,
+            Self::HomeDirInvalidUtf8 | Self::ShellInvalidUtf8 | Self::UsernameNotFound => {
+                &messages.user_lookup_error
+            }
+            Self::Credentials(_) => &messages.credentials_error,
+            Self::SessionOpen(_) => &messages.session_error,
+            Self::PasswordChangeAborted => &messages.password_change_aborted,
+            Self::PasswordChange(_) => &messages.password_change_failed,
+        }
+    }
+}
+
+/// The answers to the blind prompts of `pam_chauthtok`
+///
+/// The prompt text differs between modules and languages, so the answer is chosen by the order of
+/// the prompts. The first blind prompt asks for the current password. The following ones ask for
+/// the new password and its confirmation.
+struct PasswordChangeAnswers {
+    change: PasswordChange,
+    current_given: bool,
+    new_given: u8,
+}
+
+impl PasswordChangeAnswers {
+    /// Maximum number of times the new password is handed out without an error in between. This
+    /// is the new password and its confirmation.
+    const NEW_PASSWORD_LIMIT: u8 = 2;
+
+    fn new(change: PasswordChange) -> Self {
+        Self {
+            change,
+            current_given: false,
+            new_given: 0,
+        }
+    }
+
+    fn answer(&mut self) -> Option<String> {
+        if !self.current_given {
+            self.current_given = true;
+            return Some(self.change.current.clone());
+        }
+
+        if self.new_given == Self::NEW_PASSWORD_LIMIT {
+            info!("PAM asked for the new password again. Aborting the password change");
+            return None;
+        }
+
+        self.new_given += 1;
+        Some(self.change.new.clone())
+    }
+
+    /// A module reported an error (e.g. the confirmation did not match) and asks for the new
+    /// password and its confirmation again
+    fn reset(&mut self) {
+        self.new_given = 0;
+    }
+}
+
+/// The data that is handed to the PAM conversation function
+struct ConversationData {
+    /// The password from the login form. This is used to answer the first blind prompt.
+    password: Option<String>,
+    /// The answers used while changing an expired password
+    password_change: Option<PasswordChangeAnswers>,
+    conversation: Box<dyn AuthConversation>,
+}
+
+impl ConversationData {
+    fn respond(&mut self, style: PamMessageStyle, message: &str) -> Result<Option<String>, ()> {
+        match style {
+            PamMessageStyle::PROMPT_ECHO_OFF => {
+                if let Some(answers) = self.password_change.as_mut() {
+                    return answers.answer().map(Some).ok_or(());
+                }
+
+                match self.password.take() {
+                    Some(password) => Ok(Some(password)),
+                    None => self.conversation.prompt(message, false).map(Some).ok_or(()),
+                }
+            }
+            PamMessageStyle::PROMPT_ECHO_ON => {
+                self.conversation.prompt(message, true).map(Some).ok_or(())
+            }
+            PamMessageStyle::TEXT_INFO => {
+                info!("PAM info message: '{message}'");
+                self.conversation.info(message);
+                Ok(None)
+            }
+            PamMessageStyle::ERROR_MSG => {
+                warn!("PAM error message: '{message}'");
+                if let Some(answers) = self.password_change.as_mut() {
+                    answers.reset();
+                }
+                self.conversation.error(message);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Free the responses that have been filled so far
+unsafe fn free_responses(responses: *mut PamResponse, count: usize) {
+    for i in 0..count {
+        let response = responses.add(i);
+        if !(*response).resp.is_null() {
+            libc::free((*response).resp as *mut c_void);
+        }
+    }
+    libc::free(responses as *mut c_void);
+}
+
+/// The conversation function that PAM calls to communicate with the user
+extern "C" fn converse(
+    num_msg: c_int,
+    msg: *mut *mut PamMessage,
+    out_resp: *mut *mut PamResponse,
+    appdata_ptr: *mut c_void,
+) -> c_int {
+    if num_msg <= 0 || msg.is_null() || out_resp.is_null() || appdata_ptr.is_null() {
+        return PamReturnCode::CONV_ERR as c_int;
+    }
+
+    let count = num_msg as usize;
+
+    // PAM takes ownership of the responses and frees them with `free`.
+    let responses =
+        unsafe { libc::calloc(count, std::mem::size_of::<PamResponse>()) } as *mut PamResponse;
+    if responses.is_null() {
+        return PamReturnCode::BUF_ERR as c_int;
+    }
+
+    // SAFETY: The `appdata_ptr` is always the boxed `ConversationData` of the `PamSession`.
+    let data = unsafe { &mut *(appdata_ptr as *mut ConversationData) };
+
+    for i in 0..count {
+        // SAFETY: PAM guarantees that there are `num_msg` messages.
+        let message = unsafe { &**msg.add(i) };
+        let text = if message.msg.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(message.msg) }
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        let response = match data.respond(PamMessageStyle::from(message.msg_style), &text) {
+            Ok(response) => response,
+            Err(()) => {
+                info!("PAM conversation was aborted");
+                unsafe { free_responses(responses, count) };
+                return PamReturnCode::CONV_ERR as c_int;
+            }
+        };
+
+        if let Some(response) = response {
+            let Ok(response) = CString::new(response) else {
+                unsafe { free_responses(responses, count) };
+                return PamReturnCode::CONV_ERR as c_int;
+            };
+
+            let duplicate = unsafe { libc::strdup(response.as_ptr()) };
+            if duplicate.is_null() {
+                unsafe { free_responses(responses, count) };
+                return PamReturnCode::BUF_ERR as c_int;
+            }
+
+            unsafe { (*responses.add(i)).resp = duplicate };
+        }
+    }
+
+    unsafe { *out_resp = responses };
+
+    PamReturnCode::SUCCESS as c_int
+}
+
+/// A handle to a PAM transaction
+///
+/// When this is dropped, the session is closed, the credentials are deleted and the transaction
+/// is ended.
+pub struct PamSession {
+    handle: *mut PamHandle,
+
+    // This needs to stay alive as long as the handle, since PAM holds a pointer to it.
+    conversation: Box<ConversationData>,
+
+    has_credentials: bool,
+    has_open_session: bool,
+}
+
+impl PamSession {
+    fn start(
+        pam_service: &str,
+        username: &str,
+        mut conversation: Box<ConversationData>,
+    ) -> Result<Self, PamReturnCode> {
+        let pam_conversation = PamConversation {
+            conv: Some(converse),
+            data_ptr: &mut *conversation as *mut ConversationData as *mut c_void,
+        };
+
+        let mut handle: *mut PamHandle = ptr::null_mut();
+        match pam_sys::start(pam_service, Some(username), &pam_conversation, &mut handle) {
+            PamReturnCode::SUCCESS if !handle.is_null() => Ok(Self {
+                handle,
+                conversation,
+                has_credentials: false,
+                has_open_session: false,
+            }),
+            PamReturnCode::SUCCESS => Err(PamReturnCode::SYSTEM_ERR),
+            code => Err(code),
+        }
+    }
+
+    fn handle(&mut self) -> &mut PamHandle {
+        // SAFETY: The handle is checked to be non-null when the session is started and stays
+        // valid until `pam_end` is called in `drop`.
+        unsafe { &mut *self.handle }
+    }
+
+    fn check(code: PamReturnCode) -> Result<(), PamReturnCode> {
+        match code {
+            PamReturnCode::SUCCESS => Ok(()),
+            code => Err(code),
+        }
+    }
+
+    fn set_item(&mut self, item_type: PamItemType, value: &str) -> Result<(), PamReturnCode> {
+        let value = CString::new(value).map_err(|_| PamReturnCode::BUF_ERR)?;
+
+        // SAFETY: PAM copies the string, so it only has to live for the duration of the call.
+        let item = unsafe { &*(value.as_ptr() as *const c_void) };
+        Self::check(pam_sys::set_item(self.handle(), item_type, item))
+    }
+
+    fn putenv(&mut self, key: &str, value: &str) -> Result<(), PamReturnCode> {
+        Self::check(pam_sys::putenv(self.handle(), &format!("{key}={value}")))
+    }
+
+    /// Make the information about the session visible to the PAM modules (e.g. `pam_systemd` and
+    /// `pam_loginuid`) before the session is opened
+    fn set_session_hints(&mut self, hints: &SessionHints) {
+        let tty = format!("tty{}", hints.tty);
+
+        let mut items = vec![(PamItemType::TTY, tty)];
+        if let Some(display) = &hints.display {
+            items.push((PamItemType::XDISPLAY, display.clone()));
+        }
+
+        for (item_type, value) in items {
+            if let Err(code) = self.set_item(item_type, &value) {
+                self.log_failure(&format!("Setting PAM item {item_type}"), code);
+            }
+        }
+
+        let variables = [
+            ("XDG_SESSION_TYPE", hints.session_type.to_string()),
+            ("XDG_SESSION_CLASS", hints.session_class.to_string()),
+            ("XDG_SEAT", hints.seat.clone()),
+            ("XDG_VTNR", hints.tty.to_string()),
+        ];
+
+        for (key, value) in variables {
+            if let Err(code) = self.putenv(key, &value) {
+                self.log_failure(&format!("Setting PAM variable {key}"), code);
+            }
+        }
+    }
+
+    /// Log the reason PAM gives for a failed call
+    fn log_failure(&mut self, call: &str, code: PamReturnCode) {
+        let reason = pam_sys::strerror(self.handle(), code)
+            .unwrap_or("Unknown error")
+            .to_string();
+        warn!("{call} failed with code {code}. Reason: {reason}");
+    }
+
+    fn authenticate(&mut self) -> Result<(), PamReturnCode> {
+        Self::check(pam_sys::authenticate(self.handle(), PamFlag::NONE))
+    }
+
+    fn acct_mgmt(&mut self) -> Result<(), PamReturnCode> {
+        Self::check(pam_sys::acct_mgmt(self.handle(), PamFlag::NONE))
+    }
+
+    fn chauthtok(&mut self) -> Result<(), PamReturnCode> {
+        Self::check(pam_sys::chauthtok(
+            self.handle(),
+            PamFlag::CHANGE_EXPIRED_AUTHTOK,
+        ))
+    }
+
+    /// Let the user choose a new password and change it with `pam_chauthtok`
+    fn change_expired_password(&mut self) -> Result<(), AuthenticationError> {
+        let change = self
+            .conversation
+            .conversation
+            .change_password()
+            .ok_or(AuthenticationError::PasswordChangeAborted)?;
+
+        self.conversation.password_change = Some(PasswordChangeAnswers::new(change));
+
+        let result = self.chauthtok();
+        self.conversation.password_change = None;
+
+        result.map_err(|code| {
+            self.log_failure("pam_chauthtok", code);
+            AuthenticationError::PasswordChange(code)
+        })
+    }
+
+    fn open_session(&mut self) -> Result<(), AuthenticationError> {
+        Self::check(pam_sys::setcred(self.handle(), PamFlag::ESTABLISH_CRED)).map_err(|code| {
+            self.log_failure("pam_setcred", code);
+            AuthenticationError::Credentials(code)
+        })?;
+        self.has_credentials = true;
+
+        Self::check(pam_sys::open_session(self.handle(), PamFlag::NONE)).map_err(|code| {
+            self.log_failure("pam_open_session", code);
+            AuthenticationError::SessionOpen(code)
+        })?;
+        self.has_open_session = true;
+
+        // Follow OpenSSH and call pam_setcred before and after pam_open_session
+        Self::check(pam_sys::setcred(self.handle(), PamFlag::REINITIALIZE_CRED)).map_err(|code| {
+            self.log_failure("pam_setcred", code);
+            AuthenticationError::Credentials(code)
+        })
+    }
+
+    /// Fetch the PAM environment (e.g. set by `pam_systemd` or `pam_env`)
+    fn get_environment(&mut self) -> Vec<(String, String)> {
+        let mut environment = Vec::new();
+
+        let env_list = pam_sys::getenvlist(self.handle());
+        if env_list.is_null() {
+            return environment;
+        }
+
+        let mut index = 0;
+        loop {
+            // SAFETY: The list is terminated by a NULL pointer.
+            let entry = unsafe { *env_list.add(index) };
+            if entry.is_null() {
+                break;
+            }
+            index += 1;
+
+            let entry = unsafe { CStr::from_ptr(entry) }.to_string_lossy();
+            if let Some((key, value)) = entry.split_once('=') {
+                environment.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        unsafe { pam_sys::raw::pam_misc_drop_env(env_list as *mut *mut libc::c_char) };
+
+        environment
+    }
+}
+
+impl Drop for PamSession {
+    fn drop(&mut self) {
+        if self.has_open_session {
+            pam_sys::close_session(self.handle(), PamFlag::NONE);
+        }
+
+        let code = if self.has_credentials {
+            pam_sys::setcred(self.handle(), PamFlag::DELETE_CRED)
+        } else {
+            PamReturnCode::SUCCESS
+        };
+
+        pam_sys::end(self.handle(), code);
+    }
+}
+
+/// Authentication with the system's PAM stack
+pub struct PamBackend {
+    pam_service: String,
+}
+
+impl PamBackend {
+    pub fn new(pam_service: &str) -> Self {
+        Self {
+            pam_service: pam_service.to_string(),
+        }
+    }
+}
+
+impl AuthBackend for PamBackend {
+    fn open_session(
+        &self,
+        username: &str,
+        password: &str,
+        hints: &SessionHints,
+        conversation: Box<dyn AuthConversation>,
+    ) -> Result<AuthUserInfo, AuthenticationError> {
+        open_session(username, password, &self.pam_service, hints, conversation)
+    }
+}
+
+fn open_session(
     username: &str,
     password: &str,
     pam_service: &str,
-) -> Result<AuthUserInfo<'a>, AuthenticationError> {
+    hints: &SessionHints,
+    conversation: Box<dyn AuthConversation>,
+) -> Result<AuthUserInfo, AuthenticationError> {
     info!("Started opening session");
 
-    let mut authenticator = Authenticator::with_password(pam_service)
-        .map_err(|_| AuthenticationError::PamService(pam_service.to_string()))?;
+    let conversation = Box::new(ConversationData {
+        password: Some(password.to_string()),
+        password_change: None,
+        conversation,
+    });
+
+    let mut session = PamSession::start(pam_service, username, conversation)
+        .map_err(|code| AuthenticationError::PamService(pam_service.to_string(), code))?;
 
-    info!("Gotten Authenticator");
+    info!("Started PAM transaction");
 
-    // Authenticate the user
-    authenticator
-        .get_handler()
-        .set_credentials(username, password);
+    session.set_session_hints(hints);
 
-    info!("Got handler");
+    // Authenticate the user
+    session.authenticate().map_err(|code| {
+        session.log_failure("pam_authenticate", code);
+        AuthenticationError::Authentication(code)
+    })?;
 
     // Validate the account
-    authenticator
-        .authenticate()
-        .map_err(|_| AuthenticationError::AccountValidation)?;
+    match session.acct_mgmt() {
+        Ok(()) => {}
+        Err(PamReturnCode::NEW_AUTHTOK_REQD) => {
+            info!("Password of '{username}' expired. Requesting a new password");
+            session.change_expired_password()?;
+            info!("Changed expired password");
+        }
+        Err(code) => {
+            session.log_failure("pam_acct_mgmt", code);
+            return Err(AuthenticationError::AccountValidation(code));
+        }
+    }
 
     info!("Validated account");
 
@@ -76,15 +539,14 @@ pub fn open_session<'a>(
         .ok_or(AuthenticationError::ShellInvalidUtf8)?
         .to_string();
 
-    authenticator
-        .open_session()
-        .map_err(|_| AuthenticationError::SessionOpen)?;
+    session.open_session()?;
+    let environment = session.get_environment();
 
     info!("Opened session");
 
-    // NOTE: Logout happens automatically here with `drop` of authenticator
+    // NOTE: Logout happens automatically here with `drop` of the session
     Ok(AuthUserInfo {
-        authenticator,
+        session: BackendSession::Pam(session),
 
         username: username.to_string(),
         uid,
@@ -92,5 +554,42 @@ pub fn open_session<'a>(
         all_gids,
         home_dir,
         shell,
+        environment,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answers() -> PasswordChangeAnswers {
+        PasswordChangeAnswers::new(PasswordChange {
+            current: "current".to_string(),
+            new: "new".to_string(),
+        })
+    }
+
+    #[test]
+    fn password_change_prompt_order() {
+        let mut answers = answers();
+
+        assert_eq!(answers.answer().as_deref(), Some("current"));
+        assert_eq!(answers.answer().as_deref(), Some("new"));
+        assert_eq!(answers.answer().as_deref(), Some("new"));
+        assert_eq!(answers.answer(), None);
+    }
+
+    #[test]
+    fn password_change_retry_after_error() {
+        let mut answers = answers();
+
+        assert_eq!(answers.answer().as_deref(), Some("current"));
+        assert_eq!(answers.answer().as_deref(), Some("new"));
+        assert_eq!(answers.answer().as_deref(), Some("new"));
+
+        answers.reset();
+        assert_eq!(answers.answer().as_deref(), Some("new"));
+        assert_eq!(answers.answer().as_deref(), Some("new"));
+        assert_eq!(answers.answer(), None);
+    }
+}
@@ -1,111 +1,346 @@
+#[cfg(target_env = "gnu")]
+const WTMP_PATH: &str = "/var/log/wtmp";
+#[cfg(target_env = "gnu")]
+const BTMP_PATH: &str = "/var/log/btmp";
+#[cfg(target_env = "gnu")]
+const LASTLOG_PATH: &str = "/var/log/lastlog";
+
+#[cfg(target_env = "gnu")]
+extern "C" {
+    // Not exposed by the libc crate for glibc
+    fn updwtmpx(wtmpx_file: *const libc::c_char, utmpx: *const libc::utmpx);
+}
+
 pub struct UtmpxSession {
     #[cfg(target_env = "gnu")]
     session: libc::utmpx,
 }
 
+/// Copy a string into a fixed size C character array, truncating it if needed
 #[cfg(target_env = "gnu")]
-pub fn add_utmpx_entry(username: &str, tty: u8, pid: u32) -> UtmpxSession {
-    log::info!("Adding UTMPX record");
+fn copy_to_c_chars(dest: &mut [libc::c_char], src: &str) {
+    for (d, b) in dest.iter_mut().zip(src.as_bytes()) {
+        *d = *b as libc::c_char;
+    }
+}
+
+/// Read a string from a fixed size C character array. The array is only NUL-terminated if the
+/// string is shorter than the array.
+#[cfg(target_env = "gnu")]
+fn string_from_c_chars(src: &[libc::c_char]) -> String {
+    let bytes = src
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect::<Vec<_>>();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(target_env = "gnu")]
+fn set_time_to_now(entry: &mut libc::utmpx) {
+    use std::time::SystemTime;
+
+    let epoch_duration = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_else(|_| {
+            log::error!("Invalid System Time");
+            std::process::exit(1);
+        })
+        .as_micros();
+
+    entry.ut_tv.tv_sec = (epoch_duration / 1_000_000).try_into().unwrap_or_else(|_| {
+        log::error!("Invalid System Time (TV_SEC Overflow)");
+        std::process::exit(1);
+    });
+    entry.ut_tv.tv_usec = (epoch_duration % 1_000_000).try_into().unwrap_or_else(|_| {
+        log::error!("Invalid System Time (TV_USEC Overflow)");
+        std::process::exit(1);
+    });
+}
 
+/// Create a new record for a login on a tty
+#[cfg(target_env = "gnu")]
+fn new_entry(
+    ut_type: libc::c_short,
+    username: &str,
+    tty: u8,
+    pid: libc::pid_t,
+    host: Option<&str>,
+) -> libc::utmpx {
     // Check the MAN page for utmp for more information
     // `man utmp`
     //
     // https://man7.org/linux/man-pages/man0/utmpx.h.0p.html
     // https://github.com/fairyglade/ly/blob/master/src/login.c
-    let entry = {
-        // SAFETY: None of the fields in libc::utmpx have a drop implementation.
-        let mut s: libc::utmpx = unsafe { std::mem::zeroed() };
-
-        // ut_line    --- Device name of tty - "/dev/"
-        // ut_id      --- Terminal name suffix
-        // ut_user    --- Username
-        // ut_host    --- Hostname for remote login, or kernel version for run-level messages
-        // ut_exit    --- Exit status of a process marked as DEAD_PROCESS; not used by Linux init(1)
-        // ut_session --- Session ID (getsid(2)) used for windowing
-        // ut_tv {    --- Time entry was made
-        //     tv_sec     --- Seconds
-        //     tv_usec    --- Microseconds
-        // }
-        // ut_addr_v6 --- Internet address of remote
-
-        s.ut_type = libc::USER_PROCESS;
-        s.ut_pid = pid as libc::pid_t;
-
-        for (i, b) in username.as_bytes().iter().take(32).enumerate() {
-            s.ut_user[i] = *b as libc::c_char;
-        }
 
-        if tty > 12 {
-            log::error!("Invalid TTY");
-            std::process::exit(1);
-        }
-        let tty_c_char = (b'0' + tty) as libc::c_char;
+    // SAFETY: None of the fields in libc::utmpx have a drop implementation.
+    let mut s: libc::utmpx = unsafe { std::mem::zeroed() };
 
-        s.ut_line[0] = b't' as libc::c_char;
-        s.ut_line[1] = b't' as libc::c_char;
-        s.ut_line[2] = b'y' as libc::c_char;
-        s.ut_line[3] = tty_c_char;
+    // ut_line    --- Device name of tty - "/dev/"
+    // ut_id      --- Terminal name suffix
+    // ut_user    --- Username
+    // ut_host    --- Hostname for remote login, or kernel version for run-level messages
+    // ut_exit    --- Exit status of a process marked as DEAD_PROCESS; not used by Linux init(1)
+    // ut_session --- Session ID (getsid(2)) used for windowing
+    // ut_tv {    --- Time entry was made
+    //     tv_sec     --- Seconds
+    //     tv_usec    --- Microseconds
+    // }
+    // ut_addr_v6 --- Internet address of remote
 
-        s.ut_id[0] = tty_c_char;
+    s.ut_type = ut_type;
+    s.ut_pid = pid;
 
-        use std::time::SystemTime;
+    // Follow agetty and use the part of the line after "tty" as the id
+    let tty = tty.to_string();
+    copy_to_c_chars(&mut s.ut_line, &format!("tty{tty}"));
+    copy_to_c_chars(&mut s.ut_id, &tty);
 
-        let epoch_duration = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_else(|_| {
-                log::error!("Invalid System Time");
-                std::process::exit(1);
-            })
-            .as_micros();
+    copy_to_c_chars(&mut s.ut_user, username);
 
-        s.ut_tv.tv_sec = (epoch_duration / 1_000_000).try_into().unwrap_or_else(|_| {
-            log::error!("Invalid System Time (TV_SEC Overflow)");
-            std::process::exit(1);
-        });
-        s.ut_tv.tv_usec = (epoch_duration % 1_000_000).try_into().unwrap_or_else(|_| {
-            log::error!("Invalid System Time (TV_USEC Overflow)");
-            std::process::exit(1);
-        });
+    if let Some(host) = host {
+        copy_to_c_chars(&mut s.ut_host, host);
+    }
+
+    // SAFETY: `getsid` has no preconditions. It returns -1 if the process does not exist.
+    let sid = unsafe { libc::getsid(pid) };
+    if sid > 0 {
+        s.ut_session = sid as _;
+    }
+
+    set_time_to_now(&mut s);
 
-        s
+    s
+}
+
+/// Append a record to a wtmp-formatted file (e.g. wtmp or btmp)
+#[cfg(target_env = "gnu")]
+fn append_to_file(path: &str, entry: &libc::utmpx) {
+    let Ok(path_cstr) = std::ffi::CString::new(path) else {
+        return;
     };
 
+    // SAFETY: Both pointers are valid for the duration of the call.
+    unsafe { updwtmpx(path_cstr.as_ptr(), entry as *const libc::utmpx) };
+}
+
+/// The `struct lastlog` from glibc's `<lastlog.h>`. The libc crate does not expose it for glibc,
+/// and the size of `ll_time` differs between architectures, so it is only defined for the
+/// architectures where the layout is known.
+#[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
+#[repr(C)]
+struct Lastlog {
+    ll_time: i32,
+    ll_line: [libc::c_char; 32],
+    ll_host: [libc::c_char; 256],
+}
+
+#[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
+const _: () = assert!(std::mem::size_of::<Lastlog>() == 292);
+
+/// Update the lastlog record of the user. This is skipped on systems without a lastlog file.
+#[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
+fn update_lastlog(uid: libc::uid_t, entry: &libc::utmpx) {
+    use std::fs::OpenOptions;
+    use std::mem::size_of;
+    use std::os::unix::fs::FileExt;
+
+    let file = match OpenOptions::new().write(true).open(LASTLOG_PATH) {
+        Ok(file) => file,
+        Err(err) => {
+            log::info!("Skipping lastlog update. Reason: {err}");
+            return;
+        }
+    };
+
+    let record = Lastlog {
+        // The type of `tv_sec` differs between architectures
+        #[allow(clippy::unnecessary_cast)]
+        ll_time: entry.ut_tv.tv_sec as i32,
+        ll_line: entry.ut_line,
+        ll_host: entry.ut_host,
+    };
+
+    // SAFETY: `Lastlog` is `repr(C)` and has no padding, so all of its bytes are initialized.
+    let bytes = unsafe {
+        std::slice::from_raw_parts(&record as *const Lastlog as *const u8, size_of::<Lastlog>())
+    };
+
+    let offset = u64::from(uid) * size_of::<Lastlog>() as u64;
+    if let Err(err) = file.write_at(bytes, offset) {
+        log::warn!("Failed to update lastlog. Reason: {err}");
+    }
+}
+
+/// The layout of the lastlog records is not known for this architecture, so the lastlog is not
+/// updated.
+#[cfg(all(target_env = "gnu", not(target_arch = "x86_64")))]
+fn update_lastlog(_uid: libc::uid_t, _entry: &libc::utmpx) {
+    log::info!("Skipping lastlog update. The record layout of this architecture is unknown");
+}
+
+#[cfg(target_env = "gnu")]
+pub fn add_utmpx_entry(
+    username: &str,
+    uid: libc::uid_t,
+    tty: u8,
+    pid: u32,
+    host: Option<&str>,
+) -> UtmpxSession {
+    log::info!("Adding UTMPX record");
+
+    let entry = new_entry(libc::USER_PROCESS, username, tty, pid as libc::pid_t, host);
+
     unsafe {
         libc::setutxent();
         libc::pututxline(&entry as *const libc::utmpx);
+        libc::endutxent();
     };
 
+    append_to_file(WTMP_PATH, &entry);
+    update_lastlog(uid, &entry);
+
     log::info!("Added UTMPX record");
 
     UtmpxSession { session: entry }
 }
 
 #[cfg(not(target_env = "gnu"))]
-pub fn add_utmpx_entry(_username: &str, _tty: u8, _pid: u32) -> UtmpxSession {
+pub fn add_utmpx_entry(
+    _username: &str,
+    _uid: libc::uid_t,
+    _tty: u8,
+    _pid: u32,
+    _host: Option<&str>,
+) -> UtmpxSession {
     log::info!("Incompatible platform for UTMPX. Skipping...");
 
     UtmpxSession {}
 }
 
+/// Record a failed login attempt in btmp, so that it shows up in `lastb`
+#[cfg(target_env = "gnu")]
+pub fn add_btmp_entry(username: &str, tty: u8, host: Option<&str>) {
+    log::info!("Adding BTMP record");
+
+    let entry = new_entry(
+        libc::LOGIN_PROCESS,
+        username,
+        tty,
+        std::process::id() as libc::pid_t,
+        host,
+    );
+
+    append_to_file(BTMP_PATH, &entry);
+}
+
+#[cfg(not(target_env = "gnu"))]
+pub fn add_btmp_entry(_username: &str, _tty: u8, _host: Option<&str>) {
+    log::info!("Incompatible platform for BTMP. Skipping...");
+}
+
+/// Mark a login record as logged out in utmp and wtmp
+#[cfg(target_env = "gnu")]
+fn mark_dead(entry: &mut libc::utmpx) {
+    // The line and id are kept, so that the record replaces the login record in utmp and can be
+    // matched with the login record in wtmp.
+    entry.ut_type = libc::DEAD_PROCESS;
+
+    entry.ut_user = <[libc::c_char; 32]>::default();
+    entry.ut_host = [0; 256];
+
+    set_time_to_now(entry);
+
+    unsafe {
+        libc::setutxent();
+        libc::pututxline(entry as *const libc::utmpx);
+        libc::endutxent();
+    }
+
+    append_to_file(WTMP_PATH, entry);
+}
+
+#[cfg(target_env = "gnu")]
+fn is_process_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: Sending signal 0 only checks whether the process exists.
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    // EPERM means that the process exists, but we cannot signal it
+    std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// Mark the login records on the tty whose process is gone as logged out. These are left behind
+/// when lemurs is killed or crashes while a session is running.
+#[cfg(target_env = "gnu")]
+pub fn clean_stale_entries(tty: u8) {
+    log::info!("Looking for stale UTMPX records on tty{tty}");
+
+    let mut line = <[libc::c_char; 32]>::default();
+    copy_to_c_chars(&mut line, &format!("tty{tty}"));
+
+    let mut stale = Vec::new();
+
+    unsafe {
+        libc::setutxent();
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+
+            // SAFETY: The entry is valid until the next call to `getutxent`, so it is copied.
+            let entry = *entry;
+            if entry.ut_type == libc::USER_PROCESS
+                && entry.ut_line == line
+                && !is_process_alive(entry.ut_pid)
+            {
+                stale.push(entry);
+            }
+        }
+        libc::endutxent();
+    }
+
+    for mut entry in stale {
+        log::info!(
+            "Cleaning up stale UTMPX record of '{}' with pid {}",
+            string_from_c_chars(&entry.ut_user),
+            entry.ut_pid
+        );
+
+        mark_dead(&mut entry);
+    }
+}
+
+#[cfg(not(target_env = "gnu"))]
+pub fn clean_stale_entries(_tty: u8) {
+    log::info!("Incompatible platform for UTMPX. Skipping...");
+}
+
 #[cfg(target_env = "gnu")]
 impl Drop for UtmpxSession {
     fn drop(&mut self) {
-        let entry = &mut self.session;
-
         log::info!("Removing UTMPX record");
 
-        entry.ut_type = libc::DEAD_PROCESS;
+        mark_dead(&mut self.session);
+    }
+}
 
-        entry.ut_line = <[libc::c_char; 32]>::default();
-        entry.ut_user = <[libc::c_char; 32]>::default();
+#[cfg(all(test, target_env = "gnu"))]
+mod tests {
+    use super::*;
 
-        entry.ut_tv.tv_usec = 0;
-        entry.ut_tv.tv_sec = 0;
+    #[test]
+    fn c_chars() {
+        let mut user = [0 as libc::c_char; 32];
+        copy_to_c_chars(&mut user, "demo");
+        assert_eq!(string_from_c_chars(&user), "demo");
 
-        unsafe {
-            libc::setutxent();
-            libc::pututxline(entry as *const libc::utmpx);
-            libc::endutxent();
-        }
+        // A string that fills the whole array has no NUL terminator
+        let name = "a".repeat(32);
+        copy_to_c_chars(&mut user, &name);
+        assert_eq!(string_from_c_chars(&user), name);
     }
 }
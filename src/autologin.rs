@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+use crate::auth::{AuthConversation, PasswordChange};
+use crate::config::Config;
+use crate::post_login::{get_envs, SessionUser};
+use crate::{start_session, Hooks, StartSessionError};
+
+/// The file that marks that autologin already happened during this boot. `/run` is cleared on
+/// every boot.
+const FIRST_BOOT_MARKER_PATH: &str = "/run/lemurs/autologin";
+
+/// Sessions that exit quicker than this are assumed to be broken, and are not started again.
+const MIN_RELOGIN_SESSION_DURATION: Duration = Duration::from_secs(5);
+
+/// The conversation used for autologin. There is no user to answer prompts, so every prompt is
+/// aborted.
+struct AutologinConversation;
+
+impl AuthConversation for AutologinConversation {
+    fn prompt(&mut self, message: &str, _echo: bool) -> Option<String> {
+        warn!("Aborting prompt '{message}' during autologin");
+        None
+    }
+
+    fn info(&mut self, message: &str) {
+        info!("PAM message during autologin: {message}");
+    }
+
+    fn error(&mut self, message: &str) {
+        warn!("PAM error during autologin: {message}");
+    }
+
+    fn change_password(&mut self) -> Option<PasswordChange> {
+        warn!("Password expired. Cannot change the password during autologin");
+        None
+    }
+}
+
+/// Check whether autologin already happened during this boot
+fn happened_this_boot() -> bool {
+    Path::new(FIRST_BOOT_MARKER_PATH).exists()
+}
+
+/// Mark that autologin happened during this boot. This is only done once the session started, so
+/// that a failed attempt does not use up the autologin of this boot.
+fn mark_first_boot() {
+    let marker = Path::new(FIRST_BOOT_MARKER_PATH);
+
+    if let Some(parent) = marker.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            warn!(
+                "Failed to create directory '{}'. Reason: {err}",
+                parent.display()
+            );
+        }
+    }
+
+    if let Err(err) = fs::write(marker, b"") {
+        warn!(
+            "Failed to create autologin marker '{}'. Reason: {err}",
+            marker.display()
+        );
+    }
+}
+
+/// Log in automatically as configured in the `[autologin]` section.
+///
+/// This returns when the login form should be shown, either because the session exited or
+/// because autologin failed.
+pub fn run(config: &Config) {
+    let autologin = &config.autologin;
+
+    if autologin.only_first_boot && happened_this_boot() {
+        info!("Skipping autologin, because it already happened during this boot");
+        return;
+    }
+
+    if autologin.user.is_empty() {
+        error!("Autologin is enabled, but no user is configured");
+        return;
+    }
+
+    let envs = get_envs(config, SessionUser::from_username(&autologin.user).as_ref());
+    let post_login_env = if autologin.environment.is_empty() {
+        envs.into_iter().next()
+    } else {
+        envs.into_iter()
+            .find(|(title, _)| title == &autologin.environment)
+    };
+
+    let Some((title, post_login_env)) = post_login_env else {
+        error!(
+            "Failed to find the autologin environment '{}'",
+            autologin.environment
+        );
+        return;
+    };
+
+    let mut config = config.clone();
+    config.pam_service = autologin.pam_service.clone();
+
+    let hooks = Hooks {
+        pre_validate: None,
+        pre_auth: None,
+        pre_environment: None,
+        pre_wait: autologin
+            .only_first_boot
+            .then_some(&mark_first_boot as &dyn Fn()),
+        pre_return: None,
+    };
+
+    loop {
+        info!(
+            "Automatically logging in '{}' to environment '{title}'",
+            autologin.user
+        );
+
+        let started = Instant::now();
+
+        match start_session(
+            &autologin.user,
+            "",
+            &post_login_env,
+            &hooks,
+            &config,
+            false,
+            Box::new(AutologinConversation),
+        ) {
+            Ok(()) => {}
+            Err(StartSessionError::AuthenticationError(err)) => {
+                error!("Autologin failed. Reason: {err}");
+                return;
+            }
+            Err(StartSessionError::EnvironmentStartError(err)) => {
+                error!("Starting autologin environment failed. Reason: {err}");
+                return;
+            }
+        }
+
+        if !autologin.relogin {
+            return;
+        }
+
+        if started.elapsed() < MIN_RELOGIN_SESSION_DURATION {
+            warn!("Autologin session exited immediately. Not logging in again");
+            return;
+        }
+    }
+}
@@ -3,6 +3,8 @@ use std::error::Error;
 use std::fmt::Display;
 use std::path::PathBuf;
 
+use crate::config::AuthBackendKind;
+
 pub fn usage() {
     print!(
         r###"Lemurs {}
@@ -18,9 +20,11 @@ OPTIONS:
         --no-log
         --preview
         --tty <N>             Override the configured TTY number
-        --xsessions <DIR>     Override the path to /usr/share/xsessions
-        --wlsessions <DIR>    Override the path to /usr/share/wayland-sessions
+        --xsessions <DIR>     Only search this directory for X11 session entries
+        --wlsessions <DIR>    Only search this directory for Wayland session entries
         --initial-path <PATH> Override the initial value of the PATH variable
+        --auth-backend <NAME> Override the authentication backend (pam, or mock with --preview)
+        --mock-users <FILE>   Override the users file of the mock authentication backend
     -V, --version             Print version information
 
 SUBCOMMANDS:
@@ -43,6 +47,8 @@ pub struct Cli {
     pub xsessions: Option<PathBuf>,
     pub wlsessions: Option<PathBuf>,
     pub initial_path: Option<String>,
+    pub auth_backend: Option<AuthBackendKind>,
+    pub mock_users: Option<PathBuf>,
 }
 
 pub enum Commands {
@@ -56,6 +62,7 @@ pub enum Commands {
 pub enum CliError {
     MissingArgument(&'static str),
     InvalidTTY,
+    InvalidAuthBackend,
     InvalidArgument(String),
 }
 
@@ -68,6 +75,12 @@ impl Display for CliError {
             CliError::InvalidTTY => {
                 write!(f, "Given an invalid TTY number (only 1-12 are allowed)")
             }
+            CliError::InvalidAuthBackend => {
+                write!(
+                    f,
+                    "Given an invalid authentication backend (only pam and mock are allowed)"
+                )
+            }
             CliError::InvalidArgument(arg) => {
                 write!(f, "Given an invalid flag or command '{arg}'")
             }
@@ -89,6 +102,8 @@ impl Cli {
             xsessions: None,
             wlsessions: None,
             initial_path: None,
+            auth_backend: None,
+            mock_users: None,
         };
 
         let mut args = args().skip(1).enumerate();
@@ -137,6 +152,21 @@ impl Cli {
                         .ok_or(CliError::MissingArgument("initial-path"))?;
                     cli.initial_path = Some(arg);
                 }
+                (_, "--auth-backend") => {
+                    let (_, arg) = args
+                        .next()
+                        .ok_or(CliError::MissingArgument("auth-backend"))?;
+                    cli.auth_backend = Some(match arg.trim() {
+                        "pam" => AuthBackendKind::Pam,
+                        "mock" => AuthBackendKind::Mock,
+                        _ => return Err(CliError::InvalidAuthBackend),
+                    });
+                }
+                (_, "--mock-users") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("mock-users"))?;
+                    let arg = PathBuf::from(arg);
+                    cli.mock_users = Some(arg);
+                }
                 (_, arg) => return Err(CliError::InvalidArgument(arg.to_string())),
             }
         }
@@ -191,6 +191,7 @@ pub struct Variables(toml::value::Table);
 
 toml_config_struct! { Config, PartialConfig, RoughConfig,
     tty => u8,
+    seat => String,
 
     main_log_path => String,
     client_log_path => String,
@@ -198,7 +199,9 @@ toml_config_struct! { Config, PartialConfig, RoughConfig,
 
     do_log => bool,
 
+    auth_backend => AuthBackendKind,
     pam_service => String,
+    mock_users_path => String,
     system_shell => String,
     initial_path => String,
 
@@ -214,6 +217,18 @@ toml_config_struct! { Config, PartialConfig, RoughConfig,
     username_field => UsernameFieldConfig [PartialUsernameFieldConfig, RoughUsernameFieldConfig],
     password_field => PasswordFieldConfig [PartialPasswordFieldConfig, RoughPasswordFieldConfig],
 
+    authentication_messages => AuthenticationMessagesConfig [PartialAuthenticationMessagesConfig, RoughAuthenticationMessagesConfig],
+
+    autologin => AutologinConfig [PartialAutologinConfig, RoughAutologinConfig],
+
+    user_sessions => UserSessionsConfig [PartialUserSessionsConfig, RoughUserSessionsConfig],
+
+    session_wrappers => SessionWrappersConfig [PartialSessionWrappersConfig, RoughSessionWrappersConfig],
+
+    session => SessionConfig [PartialSessionConfig, RoughSessionConfig],
+    logout => LogoutConfig [PartialLogoutConfig, RoughLogoutConfig],
+    cgroup => CgroupConfig [PartialCgroupConfig, RoughCgroupConfig],
+
     x11 => X11Config [PartialX11Config, RoughX11Config],
     wayland => WaylandConfig [PartialWaylandConfig, RoughWaylandConfig],
 }
@@ -306,6 +321,8 @@ toml_config_struct! { SwitcherConfig, PartialSwitcherConfig, RoughSwitcherConfig
 
     include_tty_shell => bool,
 
+    locale => String,
+
     remember => bool,
 
     show_movers => bool,
@@ -368,6 +385,18 @@ toml_config_struct! { InputFieldStyle, PartialInputFieldStyle, RoughInputFieldSt
 toml_config_struct! { UsernameFieldConfig, PartialUsernameFieldConfig, RoughUsernameFieldConfig,
     remember => bool,
     style => InputFieldStyle [PartialInputFieldStyle, RoughInputFieldStyle],
+    user_picker => UserPickerConfig [PartialUserPickerConfig, RoughUserPickerConfig],
+}
+
+toml_config_struct! { UserPickerConfig, PartialUserPickerConfig, RoughUserPickerConfig,
+    enabled => bool,
+
+    min_uid => u32,
+    max_uid => u32,
+
+    hidden_users => Vec<String>,
+
+    show_real_names => bool,
 }
 
 toml_config_struct! { PasswordFieldConfig, PartialPasswordFieldConfig, RoughPasswordFieldConfig,
@@ -375,23 +404,138 @@ toml_config_struct! { PasswordFieldConfig, PartialPasswordFieldConfig, RoughPass
     style => InputFieldStyle [PartialInputFieldStyle, RoughInputFieldStyle],
 }
 
+toml_config_struct! { AuthenticationMessagesConfig, PartialAuthenticationMessagesConfig, RoughAuthenticationMessagesConfig,
+    invalid_credentials => String,
+    user_unknown => String,
+    max_tries => String,
+    account_expired => String,
+    account_denied => String,
+    credentials_error => String,
+    session_error => String,
+    service_error => String,
+    user_lookup_error => String,
+    password_change_aborted => String,
+    password_change_failed => String,
+}
+
+toml_config_struct! { AutologinConfig, PartialAutologinConfig, RoughAutologinConfig,
+    enabled => bool,
+    user => String,
+    environment => String,
+    pam_service => String,
+    only_first_boot => bool,
+    relogin => bool,
+}
+
+toml_config_struct! { UserSessionsConfig, PartialUserSessionsConfig, RoughUserSessionsConfig,
+    enabled => bool,
+    xsessions_paths => Vec<String>,
+    wayland_sessions_paths => Vec<String>,
+}
+
+toml_config_struct! { SessionWrappersConfig, PartialSessionWrappersConfig, RoughSessionWrappersConfig,
+    x11 => Vec<String>,
+    wayland => Vec<String>,
+    desktops => DesktopWrapperVec [PartialDesktopWrapperVec, RoughDesktopWrapperVec],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct DesktopWrapperVec(pub Vec<DesktopWrapper>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialDesktopWrapperVec(pub Vec<PartialDesktopWrapper>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughDesktopWrapperVec(pub Vec<RoughDesktopWrapper>);
+
+toml_config_struct! { DesktopWrapper, PartialDesktopWrapper, RoughDesktopWrapper,
+    id => String,
+    wrapper => Vec<String>,
+}
+
+impl Default for DesktopWrapper {
+    fn default() -> Self {
+        DesktopWrapper {
+            id: "".to_string(),
+            wrapper: Vec::new(),
+        }
+    }
+}
+
+toml_config_struct! { SessionConfig, PartialSessionConfig, RoughSessionConfig,
+    nofile => String,
+    nproc => String,
+    core => String,
+    umask => String,
+    nice => String,
+
+    x11 => SessionLimitsConfig [PartialSessionLimitsConfig, RoughSessionLimitsConfig],
+    wayland => SessionLimitsConfig [PartialSessionLimitsConfig, RoughSessionLimitsConfig],
+    tty => SessionLimitsConfig [PartialSessionLimitsConfig, RoughSessionLimitsConfig],
+}
+
+toml_config_struct! { SessionLimitsConfig, PartialSessionLimitsConfig, RoughSessionLimitsConfig,
+    nofile => String,
+    nproc => String,
+    core => String,
+    umask => String,
+    nice => String,
+}
+
+toml_config_struct! { LogoutConfig, PartialLogoutConfig, RoughLogoutConfig,
+    kill_session => bool,
+    kill_user_daemons => bool,
+    signals => Vec<String>,
+    grace_period_secs => u16,
+    sigkill => bool,
+}
+
+toml_config_struct! { CgroupConfig, PartialCgroupConfig, RoughCgroupConfig,
+    enabled => bool,
+    parent => String,
+    memory_max => String,
+    cpu_weight => String,
+    pids_max => String,
+}
+
 toml_config_struct! { X11Config, PartialX11Config, RoughX11Config,
     x11_display => String,
 
     xserver_timeout_secs => u16,
+    xserver_displayfd => bool,
 
     xserver_log_path => String,
 
     xserver_path => String,
-    xauth_path => String,
+    xserver_args => Vec<String>,
+    display_setup_command => Vec<String>,
+
+    virtual_server => bool,
+    virtual_server_path => String,
+    virtual_server_args => Vec<String>,
+    rootless => bool,
+    xserver_auth_dir => String,
+    xauthority_in_runtime_dir => bool,
 
     scripts_path => String,
     xsetup_path => String,
+    xsessions_paths => Vec<String>,
+
+    // Deprecated in favor of `xsessions_paths`
     xsessions_path => String,
+    // Deprecated, because the Xauthority files are written without the xauth binary
+    xauth_path => String,
 }
 
 toml_config_struct! { WaylandConfig, PartialWaylandConfig, RoughWaylandConfig,
     scripts_path => String,
+    wayland_sessions_paths => Vec<String>,
+
+    // Deprecated in favor of `wayland_sessions_paths`
     wayland_sessions_path => String,
 }
 
@@ -409,6 +553,14 @@ pub enum FocusBehaviour {
     Password,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub enum AuthBackendKind {
+    #[serde(rename = "pam")]
+    Pam,
+    #[serde(rename = "mock")]
+    Mock,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub enum ShellLoginFlag {
     #[serde(rename = "none")]
@@ -459,6 +611,39 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    /// Get the warnings about the deprecated options that are used. These are returned instead of
+    /// logged, because the logger is only set up after the configuration is loaded.
+    ///
+    /// The deprecated session directories are still used. They are searched before the
+    /// directories of the options that replaced them.
+    pub fn deprecation_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if !self.x11.xsessions_path.is_empty() {
+            warnings.push(
+                "`x11.xsessions_path` is deprecated. Use `x11.xsessions_paths` instead".to_string(),
+            );
+        }
+
+        if !self.x11.xauth_path.is_empty() {
+            warnings.push(
+                "`x11.xauth_path` is deprecated and ignored. The Xauthority files are written without the xauth binary"
+                    .to_string(),
+            );
+        }
+
+        if !self.wayland.wayland_sessions_path.is_empty() {
+            warnings.push(
+                "`wayland.wayland_sessions_path` is deprecated. Use `wayland.wayland_sessions_paths` instead"
+                    .to_string(),
+            );
+        }
+
+        warnings
+    }
+}
+
 impl PartialConfig {
     /// Facilitates the loading of the entire configuration
     pub fn from_file(
@@ -609,6 +794,35 @@ impl RoughPowerControlVec {
     }
 }
 
+impl DesktopWrapperVec {
+    pub fn merge_in_partial(&mut self, partial: PartialDesktopWrapperVec) {
+        *self = DesktopWrapperVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = DesktopWrapper::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<DesktopWrapper>>(),
+        );
+    }
+}
+
+impl RoughDesktopWrapperVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialDesktopWrapperVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialDesktopWrapper>, VariableInsertionError>>()
+            .map(PartialDesktopWrapperVec)
+    }
+}
+
 impl std::error::Error for VariableInsertionError {}
 
 macro_rules! non_string_var_insert {
@@ -675,8 +889,11 @@ non_string_var_insert! {
     bool ["boolean"],
     u8 ["unsigned 8-bit integer"],
     u16 ["unsigned 16-bit integer"],
+    u32 ["unsigned 32-bit integer"],
+    Vec<String> ["list of strings"],
     char ["character"],
     ShellLoginFlag ["shell login flag"],
+    AuthBackendKind ["authentication backend"],
     FocusBehaviour ["focus behavior"],
     SwitcherVisibility ["switcher visibility"],
     PanelPosition ["panel position"],
@@ -698,11 +915,7 @@ impl VariableInsertable for String {
             PossibleVariable::Value(s) | PossibleVariable::Variable(s) => s,
         };
 
-        loop {
-            let Some(var) = VariableIterator::new(&s).next() else {
-                break;
-            };
-
+        while let Some(var) = VariableIterator::new(&s).next() {
             let value = <PossibleVariable<String>>::try_from(
                 variables
                     .0
@@ -783,7 +996,33 @@ impl<'a> Iterator for VariableIterator<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::VariableIterator;
+    use super::{Config, PartialConfig, VariableIterator};
+
+    #[test]
+    fn deprecated_options() {
+        let mut config = Config::default();
+        assert!(config.deprecation_warnings().is_empty());
+
+        let partial: PartialConfig = toml::from_str(
+            r#"
+            [x11]
+            xsessions_path = "/usr/share/xsessions"
+            xauth_path = "/usr/bin/xauth"
+
+            [wayland]
+            wayland_sessions_path = "/usr/share/wayland-sessions"
+            "#,
+        )
+        .unwrap();
+
+        config.merge_in_partial(partial);
+        assert_eq!(config.deprecation_warnings().len(), 3);
+
+        // The deprecated directories are kept apart, so an empty list still searches
+        // `XDG_DATA_DIRS`
+        assert!(config.x11.xsessions_paths.is_empty());
+        assert_eq!(config.x11.xsessions_path, "/usr/share/xsessions");
+    }
 
     #[test]
     fn test_variable_iterator() {
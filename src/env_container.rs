@@ -10,7 +10,7 @@ use log::{error, info};
 pub struct EnvironmentContainer {
     snapshot: HashMap<String, String>,
     snapshot_pwd: String,
-    owned: HashMap<&'static str, String>,
+    owned: HashMap<String, String>,
 
     // Ensure that this is not send.
     _no_send: PhantomData<std::sync::MutexGuard<'static, ()>>,
@@ -36,7 +36,7 @@ impl EnvironmentContainer {
     }
 
     /// Set an environment variable and own the value
-    pub fn set(&mut self, key: &'static str, value: impl Into<String>) {
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
         let value = value.into();
 
         // SAFETY: We only even call this from one thread.
@@ -44,20 +44,20 @@ impl EnvironmentContainer {
 
         info!("Set environment variable '{}' to '{}'", key, value);
 
-        self.owned.insert(key, value);
+        self.owned.insert(key.to_string(), value);
     }
 
     /// Set an environment variable if it is not already set
     ///
     /// If the variable was already set, then the [`EnvironmentContainer`] considers the value as
     /// one of its own.
-    pub fn set_or_own(&mut self, key: &'static str, value: impl Into<String>) {
+    pub fn set_or_own(&mut self, key: &str, value: impl Into<String>) {
         if let Ok(value) = env::var(key) {
             info!(
                 "Skipped setting environment variable '{}'. It was already set to '{}'",
                 key, value
             );
-            self.owned.insert(key, value);
+            self.owned.insert(key.to_string(), value);
         } else {
             self.set(key, value)
         }
@@ -11,6 +11,7 @@ use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 
 mod auth;
+mod autologin;
 mod chvt;
 mod cli;
 mod config;
@@ -18,22 +19,24 @@ mod env_container;
 mod info_caching;
 mod post_login;
 mod ui;
+mod user_list;
 
 use auth::try_auth;
 use config::Config;
 use post_login::{EnvironmentStartError, PostLoginEnvironment};
 
 use crate::{
-    auth::utmpx::add_utmpx_entry,
+    auth::utmpx::{add_btmp_entry, add_utmpx_entry, clean_stale_entries},
     cli::{Cli, Commands},
 };
 
 use self::{
-    auth::AuthenticationError,
+    auth::{AuthConversation, AuthenticationError, SessionHints},
     env_container::EnvironmentContainer,
+    post_login::cgroup::SessionCgroup,
     post_login::env_variables::{
-        remove_xdg, set_basic_variables, set_display, set_seat_vars, set_session_params,
-        set_session_vars, set_xdg_common_paths,
+        remove_xdg, set_backend_variables, set_basic_variables, set_display, set_seat_vars,
+        set_session_params, set_session_vars, set_xdg_common_paths,
     },
 };
 
@@ -41,7 +44,9 @@ const DEFAULT_VARIABLES_PATH: &str = "/etc/lemurs/variables.toml";
 const DEFAULT_CONFIG_PATH: &str = "/etc/lemurs/config.toml";
 const PREVIEW_LOG_PATH: &str = "lemurs.log";
 
-fn merge_in_configuration(config: &mut Config, cli: &Cli) {
+/// Load the variables and configuration files into `config`. Returns the warnings about deprecated
+/// options, so they can be logged once the logger is running.
+fn merge_in_configuration(config: &mut Config, cli: &Cli) -> Vec<String> {
     let load_variables_path = cli
         .variables
         .as_deref()
@@ -113,13 +118,29 @@ fn merge_in_configuration(config: &mut Config, cli: &Cli) {
         }
     }
 
+    let deprecation_warnings = config.deprecation_warnings();
+
     if let Some(xsessions) = cli.xsessions.as_ref() {
-        config.x11.xsessions_path = xsessions.display().to_string();
+        config.x11.xsessions_paths = vec![xsessions.display().to_string()];
+        config.x11.xsessions_path.clear();
     }
 
     if let Some(wlsessions) = cli.wlsessions.as_ref() {
-        config.wayland.wayland_sessions_path = wlsessions.display().to_string();
+        config.wayland.wayland_sessions_paths = vec![wlsessions.display().to_string()];
+        config.wayland.wayland_sessions_path.clear();
+    }
+
+    if let Some(auth_backend) = cli.auth_backend.as_ref() {
+        config.auth_backend = auth_backend.clone();
+    } else if cli.preview {
+        config.auth_backend = config::AuthBackendKind::Mock;
+    }
+
+    if let Some(mock_users) = cli.mock_users.as_ref() {
+        config.mock_users_path = mock_users.display().to_string();
     }
+
+    deprecation_warnings
 }
 
 pub fn initialize_panic_handler() {
@@ -153,12 +174,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     });
 
     let mut config = Config::default();
-    merge_in_configuration(&mut config, &cli);
+    let deprecation_warnings = merge_in_configuration(&mut config, &cli);
 
     if let Some(cmd) = cli.command {
         match cmd {
             Commands::Envs => {
-                let envs = post_login::get_envs(&config);
+                let envs = post_login::get_envs(&config, None);
 
                 for (env_name, _) in envs.into_iter() {
                     println!("{env_name}");
@@ -197,11 +218,23 @@ fn main() -> Result<(), Box<dyn Error>> {
             &config.main_log_path
         });
         info!("Main lemurs logger is running");
+
+        for warning in &deprecation_warnings {
+            warn!("{warning}");
+        }
     } else {
         config.do_log = false;
     }
 
     if !cli.preview {
+        // The mock backend accepts fixed users and passwords, so it must never be able to start
+        // real sessions
+        if matches!(config.auth_backend, config::AuthBackendKind::Mock) {
+            eprintln!("The mock authentication backend can only be used with `--preview`");
+            error!("The mock authentication backend can only be used with `--preview`");
+            std::process::exit(1);
+        }
+
         if std::env::var("XDG_SESSION_TYPE").is_ok() {
             eprintln!(
                 "Lemurs cannot be ran without `--preview` within an existing session. Namely, `XDG_SESSION_TYPE` is set."
@@ -224,6 +257,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             config.tty = tty;
         }
 
+        clean_stale_entries(config.tty);
+
         // Switch to the proper tty
         info!("Switching to tty {}", config.tty);
 
@@ -232,6 +267,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         });
     }
 
+    if !cli.preview && config.autologin.enabled {
+        autologin::run(&config);
+    }
+
     initialize_panic_handler();
 
     // Start application
@@ -292,12 +331,17 @@ impl From<AuthenticationError> for StartSessionError {
     }
 }
 
+/// Authenticate the user and run the environment until it exits. In `preview` mode, the session
+/// is not recorded in the login records, it does not get a cgroup and it keeps the privileges of
+/// lemurs.
 fn start_session(
     username: &str,
     password: &str,
     post_login_env: &PostLoginEnvironment,
     hooks: &Hooks<'_>,
     config: &Config,
+    preview: bool,
+    conversation: Box<dyn AuthConversation>,
 ) -> Result<(), StartSessionError> {
     info!(
         "Starting new session for '{}' in environment '{:?}'",
@@ -314,13 +358,21 @@ fn start_session(
         pre_auth_hook();
     }
 
-    if matches!(post_login_env, PostLoginEnvironment::X { .. }) {
-        set_display(&config.x11.x11_display, &mut process_env);
+    let display = post_login_env.select_display(config)?;
+    if let Some(display) = &display {
+        set_display(display, &mut process_env);
     }
     set_session_params(&mut process_env, post_login_env);
     remove_xdg(&mut process_env);
 
-    let auth_session = try_auth(username, password, &config.pam_service)?;
+    let hints = SessionHints::new(config, post_login_env, display);
+    let auth_session = try_auth(username, password, config, &hints, conversation, preview)
+        .inspect_err(|err| {
+            // Preview logins are not recorded
+            if err.is_failed_login() && !preview {
+                add_btmp_entry(username, config.tty, hints.display.as_deref());
+            }
+        })?;
 
     if let Some(pre_environment_hook) = hooks.pre_environment {
         pre_environment_hook();
@@ -331,7 +383,7 @@ fn start_session(
     let homedir = &auth_session.home_dir;
     let shell = &auth_session.shell;
 
-    set_seat_vars(&mut process_env, tty);
+    set_seat_vars(&mut process_env, &config.seat, tty);
     set_session_vars(&mut process_env, uid);
     set_basic_variables(
         &mut process_env,
@@ -341,12 +393,30 @@ fn start_session(
         &config.initial_path,
     );
     set_xdg_common_paths(&mut process_env, homedir);
-
-    let spawned_environment = post_login_env.spawn(&auth_session, &mut process_env, config)?;
+    set_backend_variables(&mut process_env, &auth_session.environment);
+
+    let session_cgroup = (config.cgroup.enabled && !preview)
+        .then(|| {
+            SessionCgroup::create(&config.cgroup, tty)
+                .inspect_err(|err| {
+                    warn!("Failed to create cgroup for the session. Reason: {err}");
+                })
+                .ok()
+        })
+        .flatten();
+
+    let spawned_environment = post_login_env.spawn(
+        &auth_session,
+        &mut process_env,
+        config,
+        session_cgroup.as_ref(),
+        preview,
+    )?;
 
     let pid = spawned_environment.pid();
 
-    let utmpx_session = add_utmpx_entry(username, tty, pid);
+    let utmpx_session =
+        (!preview).then(|| add_utmpx_entry(username, uid, tty, pid, hints.display.as_deref()));
     drop(process_env);
 
     info!("Waiting for environment to terminate");
@@ -355,7 +425,7 @@ fn start_session(
         pre_wait_hook();
     }
 
-    spawned_environment.wait();
+    spawned_environment.wait(config, uid);
 
     info!("Environment terminated. Returning to Lemurs...");
 
@@ -363,8 +433,95 @@ fn start_session(
         pre_return_hook();
     }
 
+    // Kills the processes that escaped the session before the session is closed
+    drop(session_cgroup);
     drop(utmpx_session);
     drop(auth_session);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::NoConversation;
+    use crate::config::AuthBackendKind;
+    use crate::post_login::DesktopInfo;
+
+    #[test]
+    fn mock_session() {
+        // The session changes the working directory to the home directory of the user
+        let cwd = std::env::current_dir().unwrap();
+        let dir = std::env::temp_dir().join(format!("lemurs-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mock_users_path = dir.join("mock_users.toml");
+        std::fs::write(
+            &mock_users_path,
+            format!(
+                "[[users]]\nusername = \"test\"\npassword = \"test\"\nuid = 1000\ngid = 1000\nhome = \"{}\"\nshell = \"/bin/sh\"\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let mut config = Config {
+            auth_backend: AuthBackendKind::Mock,
+            mock_users_path: mock_users_path.display().to_string(),
+            do_log: false,
+            ..Default::default()
+        };
+        config.logout.kill_session = false;
+
+        // The session writes the user of its environment to a file
+        let output_path = dir.join("user");
+        let environment = PostLoginEnvironment::Wayland {
+            command: vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                format!("echo \"$USER\" > '{}'", output_path.display()),
+            ],
+            desktop: DesktopInfo {
+                id: "test".to_string(),
+                desktop_names: Vec::new(),
+            },
+        };
+        let hooks = Hooks {
+            pre_validate: None,
+            pre_auth: None,
+            pre_environment: None,
+            pre_wait: None,
+            pre_return: None,
+        };
+
+        assert!(matches!(
+            start_session(
+                "test",
+                "wrong",
+                &environment,
+                &hooks,
+                &config,
+                true,
+                Box::new(NoConversation),
+            ),
+            Err(StartSessionError::AuthenticationError(_))
+        ));
+        assert!(!output_path.exists());
+
+        let result = start_session(
+            "test",
+            "test",
+            &environment,
+            &hooks,
+            &config,
+            true,
+            Box::new(NoConversation),
+        );
+        let output = std::fs::read_to_string(&output_path);
+        std::env::set_current_dir(cwd).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(output.unwrap(), "test\n");
+    }
+}
@@ -0,0 +1,216 @@
+//! A cgroup v2 per session, for systems without a logind scope.
+//!
+//! The cgroup is created under a configurable parent and the environment is moved into it before
+//! it is started, so all processes that it forks end up in the cgroup as well. When the session
+//! ends, the remaining processes are killed with `cgroup.kill` and the cgroup is removed.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use crate::config::CgroupConfig;
+
+/// Where cgroup v2 is mounted
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const TEARDOWN_TIMEOUT_SECS: u64 = 5;
+const CHECK_INTERVAL_MILLIS: u64 = 100;
+
+/// The cgroup of a session. The cgroup is killed and removed when this is dropped.
+pub struct SessionCgroup {
+    path: PathBuf,
+    /// Opened before the environment is spawned, so that the environment can move itself into the
+    /// cgroup without allocating between `fork` and `exec`
+    procs: File,
+}
+
+/// Write a value to an interface file of a cgroup
+fn write_interface(cgroup: &Path, name: &str, value: &str) -> io::Result<()> {
+    fs::write(cgroup.join(name), value)
+}
+
+fn is_populated(cgroup: &Path) -> bool {
+    fs::read_to_string(cgroup.join("cgroup.events"))
+        .map(|events| events.lines().any(|line| line == "populated 1"))
+        .unwrap_or(false)
+}
+
+/// Kill all processes in a cgroup and remove it
+fn teardown(cgroup: &Path) {
+    if let Err(err) = write_interface(cgroup, "cgroup.kill", "1") {
+        // `cgroup.kill` only exists since Linux 5.14
+        warn!("Failed to use `cgroup.kill`. Killing the processes one by one. Reason: {err}");
+
+        if let Ok(procs) = fs::read_to_string(cgroup.join("cgroup.procs")) {
+            for pid in procs.lines().filter_map(|pid| pid.parse().ok()) {
+                let _ = kill(Pid::from_raw(pid), Signal::SIGKILL);
+            }
+        }
+    }
+
+    let start_time = Instant::now();
+    while is_populated(cgroup) {
+        if start_time.elapsed() > Duration::from_secs(TEARDOWN_TIMEOUT_SECS) {
+            error!(
+                "Processes in cgroup `{}` did not exit after being killed",
+                cgroup.display()
+            );
+            return;
+        }
+
+        thread::sleep(Duration::from_millis(CHECK_INTERVAL_MILLIS));
+    }
+
+    if let Err(err) = fs::remove_dir(cgroup) {
+        error!(
+            "Failed to remove cgroup `{}`. Reason: {err}",
+            cgroup.display()
+        );
+    }
+}
+
+/// The cgroups that need the controllers of the limits enabled for their children: the root of the
+/// cgroup hierarchy and every cgroup from there down to `parent`
+fn controller_cgroups(parent: &Path) -> Vec<&Path> {
+    let mut cgroups = parent
+        .ancestors()
+        .take_while(|cgroup| cgroup.starts_with(CGROUP_ROOT))
+        .collect::<Vec<_>>();
+    cgroups.reverse();
+
+    if cgroups.is_empty() {
+        warn!(
+            "`{}` is not below `{CGROUP_ROOT}`. Only enabling the controllers for it",
+            parent.display()
+        );
+        cgroups.push(parent);
+    }
+
+    cgroups
+}
+
+impl SessionCgroup {
+    /// Create the cgroup for the session on a tty and apply the configured limits. A cgroup that
+    /// was left behind by an earlier session on the same tty is removed first.
+    pub fn create(config: &CgroupConfig, tty: u8) -> io::Result<Self> {
+        let parent = Path::new(&config.parent);
+        let path = parent.join(format!("session-tty{tty}"));
+
+        fs::create_dir_all(parent)?;
+
+        if path.exists() {
+            warn!("Removing cgroup `{}` of an earlier session", path.display());
+            teardown(&path);
+        }
+
+        let limits = [
+            ("memory", "memory.max", &config.memory_max),
+            ("cpu", "cpu.weight", &config.cpu_weight),
+            ("pids", "pids.max", &config.pids_max),
+        ];
+
+        // The controllers of the limits have to be enabled for the children of the parent. This
+        // also requires them to be enabled for the children of every ancestor of the parent, from
+        // the root of the cgroup hierarchy down.
+        let controllers = limits
+            .iter()
+            .filter(|(_, _, value)| !value.is_empty())
+            .map(|(controller, _, _)| format!("+{controller}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !controllers.is_empty() {
+            for cgroup in controller_cgroups(parent) {
+                if let Err(err) = write_interface(cgroup, "cgroup.subtree_control", &controllers) {
+                    warn!(
+                        "Failed to enable the controllers `{controllers}` for `{}`. Reason: {err}",
+                        cgroup.display()
+                    );
+                }
+            }
+        }
+
+        fs::create_dir(&path)?;
+
+        for (_, name, value) in limits {
+            if value.is_empty() {
+                continue;
+            }
+
+            if let Err(err) = write_interface(&path, name, value) {
+                warn!("Failed to set `{name}` to `{value}`. Reason: {err}");
+            }
+        }
+
+        let procs = match OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"))
+        {
+            Ok(procs) => procs,
+            Err(err) => {
+                let _ = fs::remove_dir(&path);
+                return Err(err);
+            }
+        };
+
+        info!("Created cgroup `{}` for the session", path.display());
+
+        Ok(Self { path, procs })
+    }
+
+    /// Move the process of the command into the cgroup before it starts. This needs to happen
+    /// before the privileges are lowered.
+    pub fn add_command(&self, command: &mut Command) {
+        let procs = self.procs.as_raw_fd();
+
+        unsafe {
+            command.pre_exec(move || {
+                // Writing `0` moves the writing process
+                if libc::write(procs, b"0".as_ptr() as *const libc::c_void, 1) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+    }
+}
+
+impl Drop for SessionCgroup {
+    fn drop(&mut self) {
+        info!("Removing cgroup `{}` of the session", self.path.display());
+
+        match fs::metadata(&self.path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            _ => teardown(&self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_parent() {
+        assert_eq!(
+            controller_cgroups(Path::new("/sys/fs/cgroup/system.slice/lemurs")),
+            [
+                Path::new("/sys/fs/cgroup"),
+                Path::new("/sys/fs/cgroup/system.slice"),
+                Path::new("/sys/fs/cgroup/system.slice/lemurs"),
+            ]
+        );
+        assert_eq!(
+            controller_cgroups(Path::new("/cgroup/lemurs")),
+            [Path::new("/cgroup/lemurs")]
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! Parsing of the `.desktop` files that describe X11 and Wayland sessions.
+//!
+//! This follows the [Desktop Entry
+//! Specification](https://specifications.freedesktop.org/desktop-entry-spec/latest/) for the
+//! keys that matter to a display manager.
+
+use std::fs::{self, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+
+/// The field codes that are removed from the `Exec` key, because a session is never started with
+/// files or URLs. `%i` is only removed when it is part of a larger argument, since it expands to
+/// two arguments otherwise.
+const REMOVED_FIELD_CODES: [char; 11] = ['f', 'F', 'u', 'U', 'd', 'D', 'n', 'N', 'i', 'v', 'm'];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopEntry {
+    /// The `Name`, localized to the locale if possible
+    pub name: Option<String>,
+    /// The `Comment`, localized to the locale if possible
+    pub comment: Option<String>,
+    /// The `Icon`, localized to the locale if possible
+    pub icon: Option<String>,
+    /// The `Exec` split into arguments with the field codes expanded
+    pub exec: Vec<String>,
+    pub try_exec: Option<String>,
+    pub hidden: bool,
+    pub no_display: bool,
+    pub desktop_names: Vec<String>,
+}
+
+/// A key-value pair from the `Desktop Entry` group
+struct Entry<'a> {
+    key: &'a str,
+    locale: Option<&'a str>,
+    value: &'a str,
+}
+
+impl DesktopEntry {
+    /// Parse a desktop entry file. `locale` is in the format of `LC_MESSAGES` (e.g.
+    /// `nl_NL.UTF-8`) and is used to pick the localized `Name` and `Comment`.
+    pub fn from_file(path: &Path, locale: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("file cannot be read. Reason: {err}"))?;
+
+        Self::parse(&content, locale, &path.to_string_lossy())
+    }
+
+    /// Parse a desktop entry file from the home directory of a user. The file is only read if it
+    /// is a regular file that is owned by the user or root and that cannot be written by others,
+    /// since it is read with root privileges.
+    pub fn from_user_file(path: &Path, locale: &str, uid: libc::uid_t) -> Result<Self, String> {
+        // Do not follow symbolic links and do not block on FIFOs
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|err| format!("file cannot be opened. Reason: {err}"))?;
+
+        let metadata = file
+            .metadata()
+            .map_err(|err| format!("file cannot be inspected. Reason: {err}"))?;
+
+        if !metadata.is_file() {
+            return Err("it is not a regular file".to_string());
+        }
+
+        if metadata.uid() != uid && metadata.uid() != 0 {
+            return Err("it is not owned by the user".to_string());
+        }
+
+        if metadata.mode() & 0o022 != 0 {
+            return Err("it is writable by other users".to_string());
+        }
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+            .map_err(|err| format!("file cannot be read. Reason: {err}"))?;
+
+        Self::parse(&content, locale, &path.to_string_lossy())
+    }
+
+    /// Parse the content of a desktop entry. `location` is substituted for `%k` in `Exec`.
+    pub fn parse(content: &str, locale: &str, location: &str) -> Result<Self, String> {
+        let entries = parse_group(content)?;
+
+        let get = |key: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.key == key && entry.locale.is_none())
+                .map(|entry| entry.value)
+        };
+        let get_bool = |key: &str| match get(key).map(str::trim) {
+            None | Some("false") => Ok(false),
+            Some("true") => Ok(true),
+            Some(v) => Err(format!(
+                "'{key}' key contains '{v}', which is not a boolean"
+            )),
+        };
+
+        let name = get_localized(&entries, "Name", locale);
+        let comment = get_localized(&entries, "Comment", locale);
+        let icon = get_localized(&entries, "Icon", locale).filter(|icon| !icon.is_empty());
+
+        let exec = get("Exec").ok_or("'Exec' key cannot be found".to_string())?;
+        let exec = split_exec(
+            &unescape(exec),
+            name.as_deref().unwrap_or_default(),
+            icon.as_deref(),
+            location,
+        )?;
+        if exec.is_empty() {
+            return Err("'Exec' key is empty".to_string());
+        }
+
+        let try_exec = get("TryExec")
+            .map(unescape)
+            .filter(|try_exec| !try_exec.is_empty());
+
+        let desktop_names = get("DesktopNames")
+            .map(|desktop_names| {
+                desktop_names
+                    .split(';')
+                    .filter(|name| !name.is_empty())
+                    .map(unescape)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            name,
+            comment,
+            icon,
+            exec,
+            try_exec,
+            hidden: get_bool("Hidden")?,
+            no_display: get_bool("NoDisplay")?,
+            desktop_names,
+        })
+    }
+
+    /// Check whether the program from `TryExec` is installed. `path` is the `PATH` that is used
+    /// to search for the program if it is not an absolute path.
+    pub fn is_installed(&self, path: &str) -> bool {
+        let Some(try_exec) = &self.try_exec else {
+            return true;
+        };
+
+        if try_exec.contains('/') {
+            return is_executable(Path::new(try_exec));
+        }
+
+        path.split(':')
+            .filter(|dir| !dir.is_empty())
+            .any(|dir| is_executable(&Path::new(dir).join(try_exec)))
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Get the key-value pairs from the `Desktop Entry` group
+fn parse_group(content: &str) -> Result<Vec<Entry<'_>>, String> {
+    let mut group = None;
+    let mut found_group = false;
+    let mut entries = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let Some(name) = header.strip_suffix(']') else {
+                return Err(format!("invalid group header '{line}'"));
+            };
+
+            group = Some(name);
+            found_group |= name == DESKTOP_ENTRY_GROUP;
+            continue;
+        }
+
+        if group != Some(DESKTOP_ENTRY_GROUP) {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line '{line}' is not a key-value pair"));
+        };
+
+        let key = key.trim_end();
+        let value = value.trim_start();
+
+        let (key, locale) = match key.strip_suffix(']').and_then(|k| k.split_once('[')) {
+            Some((key, locale)) => (key.trim_end(), Some(locale)),
+            None => (key, None),
+        };
+
+        entries.push(Entry { key, locale, value });
+    }
+
+    if !found_group {
+        return Err(format!(
+            "file does not contain '{DESKTOP_ENTRY_GROUP}' group"
+        ));
+    }
+
+    Ok(entries)
+}
+
+/// Get the value of a localestring key that best matches the locale.
+///
+/// A locale of the form `lang_COUNTRY.ENCODING@MODIFIER` matches, in order of preference,
+/// `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER`, `lang` and the unlocalized key.
+fn get_localized(entries: &[Entry], key: &str, locale: &str) -> Option<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut candidates = Vec::new();
+    if !lang.is_empty() && lang != "C" && lang != "POSIX" {
+        if let (Some(country), Some(modifier)) = (country, modifier) {
+            candidates.push(format!("{lang}_{country}@{modifier}"));
+        }
+        if let Some(country) = country {
+            candidates.push(format!("{lang}_{country}"));
+        }
+        if let Some(modifier) = modifier {
+            candidates.push(format!("{lang}@{modifier}"));
+        }
+        candidates.push(lang.to_string());
+    }
+
+    candidates
+        .iter()
+        .map(|candidate| Some(candidate.as_str()))
+        .chain(std::iter::once(None))
+        .find_map(|locale| {
+            entries
+                .iter()
+                .find(|entry| entry.key == key && entry.locale == locale)
+        })
+        .map(|entry| unescape(entry.value))
+}
+
+/// Apply the escape sequences of string values
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// Split the (unescaped) `Exec` value into arguments, following the quoting rules and expanding
+/// the field codes.
+fn split_exec(
+    exec: &str,
+    name: &str,
+    icon: Option<&str>,
+    location: &str,
+) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = exec.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+
+        let Some(first) = chars.peek().copied() else {
+            break;
+        };
+
+        // A `%i` argument expands to `--icon <Icon>`, or to nothing if there is no icon
+        let mut rest = chars.clone();
+        if rest.next() == Some('%')
+            && rest.next() == Some('i')
+            && matches!(rest.peek(), None | Some(' ' | '\t'))
+        {
+            chars = rest;
+            if let Some(icon) = icon {
+                args.extend(["--icon".to_string(), icon.to_string()]);
+            }
+            continue;
+        }
+
+        if first == '"' {
+            chars.next();
+
+            let mut arg = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '`' | '$' | '\\')) => arg.push(c),
+                        Some(c) => {
+                            arg.push('\\');
+                            arg.push(c);
+                        }
+                        None => return Err("'Exec' key ends with an escape".to_string()),
+                    },
+                    Some(c) => arg.push(c),
+                    None => return Err("'Exec' key contains an unterminated quote".to_string()),
+                }
+            }
+
+            // Field codes are not expanded within quotes
+            args.push(arg);
+            continue;
+        }
+
+        let mut arg = String::new();
+        let mut only_removed_codes = true;
+        while let Some(c) = chars.next_if(|c| *c != ' ' && *c != '\t') {
+            if c != '%' {
+                arg.push(c);
+                only_removed_codes = false;
+                continue;
+            }
+
+            match chars.next() {
+                Some('%') => arg.push('%'),
+                Some('c') => arg.push_str(name),
+                Some('k') => arg.push_str(location),
+                Some(c) if REMOVED_FIELD_CODES.contains(&c) => continue,
+                Some(c) => return Err(format!("'Exec' key contains invalid field code '%{c}'")),
+                None => return Err("'Exec' key ends with '%'".to_string()),
+            }
+
+            only_removed_codes = false;
+        }
+
+        // An argument that only consisted of field codes that expand to nothing is removed
+        if !only_removed_codes {
+            args.push(arg);
+        }
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<DesktopEntry, String> {
+        DesktopEntry::parse(content, "", "/usr/share/xsessions/test.desktop")
+    }
+
+    #[test]
+    fn minimal_entry() {
+        let entry = parse("[Desktop Entry]\nName=Sway\nExec=sway\n").unwrap();
+
+        assert_eq!(entry.name.as_deref(), Some("Sway"));
+        assert_eq!(entry.exec, vec!["sway"]);
+        assert_eq!(entry.try_exec, None);
+        assert!(!entry.hidden);
+        assert!(!entry.no_display);
+
+        assert!(parse("[Desktop Entry]\nName=Sway\n").is_err());
+        assert!(parse("[Desktop Action new]\nExec=sway\n").is_err());
+    }
+
+    #[test]
+    fn other_groups_are_ignored() {
+        let entry = parse(
+            "# Comment\n[Desktop Entry]\nExec=sway\nHidden=true\n\n[Desktop Action new]\nExec=other\n",
+        )
+        .unwrap();
+
+        assert_eq!(entry.exec, vec!["sway"]);
+        assert!(entry.hidden);
+        assert!(parse("[Desktop Entry]\nExec=sway\nNoDisplay=maybe\n").is_err());
+    }
+
+    #[test]
+    fn localized_name() {
+        let content = "[Desktop Entry]\nName[nl]=Bureaublad\nName=Desktop\nName[sr@latin]=Radna površ\nName[pt_BR]=Área de trabalho\nComment[nl]=Een bureaublad\nExec=de\n";
+
+        let name = |locale| DesktopEntry::parse(content, locale, "").unwrap().name;
+
+        assert_eq!(name("").as_deref(), Some("Desktop"));
+        assert_eq!(name("C.UTF-8").as_deref(), Some("Desktop"));
+        assert_eq!(name("nl_BE.UTF-8").as_deref(), Some("Bureaublad"));
+        assert_eq!(name("sr_RS@latin").as_deref(), Some("Radna površ"));
+        assert_eq!(name("pt_BR.UTF-8").as_deref(), Some("Área de trabalho"));
+        assert_eq!(name("pt_PT.UTF-8").as_deref(), Some("Desktop"));
+
+        let entry = DesktopEntry::parse(content, "nl_NL", "").unwrap();
+        assert_eq!(entry.comment.as_deref(), Some("Een bureaublad"));
+    }
+
+    #[test]
+    fn exec_quoting() {
+        let exec = |exec: &str| parse(&format!("[Desktop Entry]\nExec={exec}\n")).map(|e| e.exec);
+
+        assert_eq!(
+            exec(r#"/usr/bin/env  FOO=bar "/opt/my de/start" --flag"#).unwrap(),
+            vec!["/usr/bin/env", "FOO=bar", "/opt/my de/start", "--flag"]
+        );
+        assert_eq!(
+            exec(r#""say \\"hi\\" \\$HOME""#).unwrap(),
+            vec![r#"say "hi" $HOME"#]
+        );
+        assert_eq!(exec(r"start\sde").unwrap(), vec!["start", "de"]);
+        assert!(exec(r#""unterminated"#).is_err());
+    }
+
+    #[test]
+    fn exec_field_codes() {
+        let exec =
+            |exec: &str| parse(&format!("[Desktop Entry]\nName=DE\nExec={exec}\n")).map(|e| e.exec);
+
+        assert_eq!(
+            exec("startde %U --name %c").unwrap(),
+            vec!["startde", "--name", "DE"]
+        );
+        assert_eq!(
+            exec("startde --from=%k 100%%").unwrap(),
+            vec![
+                "startde",
+                "--from=/usr/share/xsessions/test.desktop",
+                "100%"
+            ]
+        );
+        assert_eq!(exec("startde \"%f\"").unwrap(), vec!["startde", "%f"]);
+        assert_eq!(exec("startde %i").unwrap(), vec!["startde"]);
+        assert!(exec("%f").is_err());
+        assert!(exec("startde %x").is_err());
+    }
+
+    #[test]
+    fn exec_icon() {
+        let exec = |icon: &str, exec: &str| {
+            parse(&format!("[Desktop Entry]\nIcon={icon}\nExec={exec}\n")).map(|e| e.exec)
+        };
+
+        assert_eq!(
+            exec("my-de", "startde %i --flag").unwrap(),
+            vec!["startde", "--icon", "my-de", "--flag"]
+        );
+        assert_eq!(exec("", "startde %i").unwrap(), vec!["startde"]);
+        assert_eq!(
+            exec("my-de", "startde --x%i").unwrap(),
+            vec!["startde", "--x"]
+        );
+        assert_eq!(
+            exec("my-de", "startde \"%i\"").unwrap(),
+            vec!["startde", "%i"]
+        );
+    }
+
+    #[test]
+    fn desktop_names() {
+        let entry = parse("[Desktop Entry]\nExec=de\nDesktopNames=KDE;Plasma;\n").unwrap();
+        assert_eq!(entry.desktop_names, vec!["KDE", "Plasma"]);
+    }
+}
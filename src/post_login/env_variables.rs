@@ -16,6 +16,7 @@ pub fn remove_xdg(process_env: &mut EnvironmentContainer) {
     process_env.remove_var("XDG_SESSION_CLASS");
     process_env.remove_var("XDG_CURRENT_DESKTOP");
     process_env.remove_var("XDG_SESSION_DESKTOP");
+    process_env.remove_var("DESKTOP_SESSION");
 
     process_env.remove_var("XDG_SEAT");
     process_env.remove_var("XDG_VTNR");
@@ -40,20 +41,44 @@ pub fn set_session_params(
     process_env.set("XDG_SESSION_CLASS", "user");
     process_env.set("XDG_SESSION_TYPE", post_login_env.to_xdg_type());
 
-    // TODO: Implement
-    // process_env.set("XDG_CURRENT_DESKTOP", post_login_env.to_xdg_desktop());
-    // process_env.set("XDG_SESSION_DESKTOP", post_login_env.to_xdg_desktop());
+    if let Some(xdg_desktop) = post_login_env.to_xdg_desktop() {
+        process_env.set("XDG_CURRENT_DESKTOP", &xdg_desktop);
+    }
+
+    // Display managers like GDM and SDDM set both of these to the desktop file id
+    if let Some(desktop) = post_login_env.desktop_info() {
+        process_env.set("XDG_SESSION_DESKTOP", &desktop.id);
+        process_env.set("DESKTOP_SESSION", &desktop.id);
+    }
 }
 
-pub fn set_seat_vars(process_env: &mut EnvironmentContainer, tty: u8) {
+pub fn set_seat_vars(process_env: &mut EnvironmentContainer, seat: &str, tty: u8) {
     info!("Setting XDG Seat Variables");
 
-    process_env.set_or_own("XDG_SEAT", "seat0");
+    process_env.set_or_own("XDG_SEAT", seat);
     process_env.set_or_own("XDG_VTNR", tty.to_string());
 }
 
+/// Set the environment variables provided by the authentication backend (e.g. by `pam_systemd` or
+/// `pam_env`).
+///
+/// This should be called after all the other variables are set, so that the values of the
+/// backend take precedence over the defaults of lemurs (e.g. `PATH` set by `pam_env`).
+pub fn set_backend_variables(
+    process_env: &mut EnvironmentContainer,
+    variables: &[(String, String)],
+) {
+    info!("Setting Authentication Backend Variables");
+
+    for (key, value) in variables {
+        process_env.set(key, value);
+    }
+}
+
 // NOTE: This uid: u32 might be better set to libc::uid_t
 /// Set the XDG environment variables
+///
+/// These are only fallbacks for when the authentication backend did not provide them.
 pub fn set_session_vars(process_env: &mut EnvironmentContainer, uid: u32) {
     info!("Setting XDG Session Variables");
 
@@ -0,0 +1,316 @@
+//! Ending the processes that remain after a session ends.
+//!
+//! Every session is started in its own session (`setsid`). Processes that are forked by the
+//! session (e.g. background jobs) stay in that session. Daemons like `ssh-agent` start a session
+//! of their own, so they are found in another way:
+//!
+//! - If the session runs in a cgroup of its own (the cgroup of lemurs or the scope of logind), the
+//!   processes in that cgroup that started after the session belong to the session.
+//! - Otherwise, only with `logout.kill_user_daemons`, the processes of the user that started after
+//!   the session and that have no controlling terminal belong to the session. This also includes
+//!   such processes of the other sessions of the user. Services of the user's service manager and
+//!   sessions of root are left alone.
+//!
+//! When the session ends, these processes are signaled with the configured signals and, after a
+//! grace period, killed.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use crate::config::LogoutConfig;
+
+const CHECK_INTERVAL_MILLIS: u64 = 100;
+
+/// A process that belongs to an ended session
+struct SessionProcess {
+    pid: libc::pid_t,
+    name: String,
+}
+
+/// The fields of `/proc/<pid>/stat` that are used to find the processes of a session
+#[derive(Debug, PartialEq)]
+struct ProcessStat {
+    name: String,
+    /// Zombie processes already exited, but were not reaped yet
+    is_zombie: bool,
+    session_id: libc::pid_t,
+    /// The controlling terminal, `0` if there is none
+    tty: i32,
+    /// In clock ticks since boot
+    start_time: u64,
+}
+
+/// Parse the contents of `/proc/<pid>/stat`
+fn parse_stat(stat: &str) -> Option<ProcessStat> {
+    // The name is between parentheses and may itself contain spaces and parentheses
+    let name_start = stat.find('(')?;
+    let name_end = stat.rfind(')')?;
+    let name = stat.get(name_start + 1..name_end)?.to_string();
+
+    // The fields after the name start with the state, the parent pid, the process group, the
+    // session and the controlling terminal. The start time is the 20th field after the name.
+    let fields = stat[name_end + 1..].split_whitespace().collect::<Vec<_>>();
+
+    Some(ProcessStat {
+        name,
+        is_zombie: *fields.first()? == "Z",
+        session_id: fields.get(3)?.parse().ok()?,
+        tty: fields.get(4)?.parse().ok()?,
+        start_time: fields.get(19)?.parse().ok()?,
+    })
+}
+
+/// Get the cgroup v2 path from the contents of `/proc/<pid>/cgroup`
+fn parse_cgroup(cgroup: &str) -> Option<&str> {
+    cgroup.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+fn process_stat(pid: libc::pid_t) -> Option<ProcessStat> {
+    parse_stat(&fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)
+}
+
+fn process_cgroup(pid: libc::pid_t) -> Option<String> {
+    let cgroup = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    parse_cgroup(&cgroup).map(str::to_string)
+}
+
+/// Whether a cgroup only contains a single session. This is the case for the cgroups that lemurs
+/// creates (`session-tty<N>`) and for the scopes of logind (`session-<N>.scope`).
+fn is_session_cgroup(cgroup: &str) -> bool {
+    cgroup
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name.starts_with("session-"))
+}
+
+/// The processes of a session. This has to be created before the session leader is reaped,
+/// because it looks up when the session started.
+pub struct Session {
+    id: libc::pid_t,
+    uid: libc::uid_t,
+    /// When the session leader started, in clock ticks since boot
+    start_time: Option<u64>,
+    /// The cgroup of the session leader, if only this session runs in it
+    cgroup: Option<String>,
+    /// Whether the processes of the user without a terminal belong to the session when there is
+    /// no cgroup of the session
+    kill_user_daemons: bool,
+}
+
+impl Session {
+    /// `leader` is the pid of the session leader and `uid` the user of the session
+    pub fn new(leader: libc::pid_t, uid: libc::uid_t, config: &LogoutConfig) -> Self {
+        // The leader may already have exited. As long as it is not reaped, its stat is still
+        // available.
+        let start_time = process_stat(leader).map(|stat| stat.start_time);
+        if start_time.is_none() {
+            warn!("Failed to find out when the session started. Only ending the processes in the session");
+        }
+
+        let cgroup = process_cgroup(leader).filter(|cgroup| is_session_cgroup(cgroup));
+
+        Self {
+            id: leader,
+            uid,
+            start_time,
+            cgroup,
+            kill_user_daemons: config.kill_user_daemons,
+        }
+    }
+
+    /// Whether a process that left the session still belongs to it
+    fn is_escaped_process(&self, pid: libc::pid_t, stat: &ProcessStat) -> bool {
+        // Lemurs itself and the processes that it started before the session (e.g. the X server)
+        // are never part of the session
+        let Some(start_time) = self.start_time else {
+            return false;
+        };
+        if stat.start_time < start_time || pid == std::process::id() as libc::pid_t {
+            return false;
+        }
+
+        if let Some(session_cgroup) = &self.cgroup {
+            return process_cgroup(pid).is_some_and(|cgroup| {
+                cgroup == *session_cgroup || cgroup.starts_with(&format!("{session_cgroup}/"))
+            });
+        }
+
+        // Without a cgroup, daemons of the user are recognized by having no controlling terminal.
+        // This is not done for the user of lemurs itself, because that would include the daemons
+        // of the system.
+        if !self.kill_user_daemons || self.uid == nix::unistd::getuid().as_raw() || stat.tty != 0 {
+            return false;
+        }
+
+        let is_owned_by_user =
+            fs::metadata(format!("/proc/{pid}")).is_ok_and(|metadata| metadata.uid() == self.uid);
+        let is_user_service = process_cgroup(pid).is_some_and(|cgroup| cgroup.contains("/user@"));
+
+        is_owned_by_user && !is_user_service
+    }
+
+    /// Find all processes that belong to the session
+    fn processes(&self) -> Vec<SessionProcess> {
+        let Ok(entries) = fs::read_dir("/proc") else {
+            warn!("Failed to list the running processes");
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<libc::pid_t>().ok())
+            .filter_map(|pid| {
+                // Zombie processes already exited
+                let stat = process_stat(pid).filter(|stat| !stat.is_zombie)?;
+
+                (stat.session_id == self.id || self.is_escaped_process(pid, &stat)).then_some(
+                    SessionProcess {
+                        pid,
+                        name: stat.name,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+fn report(processes: &[SessionProcess]) -> String {
+    processes
+        .iter()
+        .map(|process| format!("{} ({})", process.name, process.pid))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn send_signal(processes: &[SessionProcess], signal: Signal) {
+    for process in processes {
+        // The process may have exited in the meantime
+        let _ = kill(Pid::from_raw(process.pid), signal);
+    }
+}
+
+/// Signal and kill the processes that remain in a session after the session leader exited
+pub fn end_session(session: &Session, config: &LogoutConfig) {
+    if !config.kill_session {
+        return;
+    }
+
+    let processes = session.processes();
+    if processes.is_empty() {
+        info!("No processes remain in the session");
+        return;
+    }
+
+    info!(
+        "{} processes remain in the session: {}",
+        processes.len(),
+        report(&processes)
+    );
+
+    for signal in &config.signals {
+        match Signal::from_str(signal) {
+            Ok(signal) => {
+                info!("Sending {signal} to the remaining processes of the session");
+                send_signal(&processes, signal);
+            }
+            Err(_) => warn!("`{signal}` is not a valid signal. Skipping..."),
+        }
+    }
+
+    let start_time = Instant::now();
+    let grace_period = Duration::from_secs(config.grace_period_secs.into());
+    let mut processes = processes;
+    while !processes.is_empty() && start_time.elapsed() < grace_period {
+        thread::sleep(Duration::from_millis(CHECK_INTERVAL_MILLIS));
+        processes = session.processes();
+    }
+
+    if processes.is_empty() {
+        info!("All remaining processes of the session exited");
+        return;
+    }
+
+    if !config.sigkill {
+        warn!(
+            "{} processes of the session are still running: {}",
+            processes.len(),
+            report(&processes)
+        );
+        return;
+    }
+
+    warn!(
+        "Killing {} processes of the session that did not exit within the grace period: {}",
+        processes.len(),
+        report(&processes)
+    );
+    send_signal(&processes, Signal::SIGKILL);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_fields() {
+        assert_eq!(
+            parse_stat(
+                "1234 (ssh-agent) S 1 1234 1234 0 -1 4194624 105 0 0 0 0 0 0 0 20 0 1 0 98765 6287360 269"
+            ),
+            Some(ProcessStat {
+                name: "ssh-agent".to_string(),
+                is_zombie: false,
+                session_id: 1234,
+                tty: 0,
+                start_time: 98765,
+            })
+        );
+        assert_eq!(
+            parse_stat("42 (a) b (c)) R 1 42 42 34817 42 4194560 0 0 0 0 0 0 0 0 20 0 1 0 500 0 0"),
+            Some(ProcessStat {
+                name: "a) b (c)".to_string(),
+                is_zombie: false,
+                session_id: 42,
+                tty: 34817,
+                start_time: 500,
+            })
+        );
+        assert_eq!(parse_stat("42 (sh) S 1 42 42 34817"), None);
+        assert_eq!(
+            parse_stat("42 (sh) Z 1 42 42 34817 42 4194560 0 0 0 0 0 0 0 0 20 0 1 0 500 0 0"),
+            Some(ProcessStat {
+                name: "sh".to_string(),
+                is_zombie: true,
+                session_id: 42,
+                tty: 34817,
+                start_time: 500,
+            })
+        );
+    }
+
+    #[test]
+    fn session_cgroups() {
+        assert_eq!(
+            parse_cgroup("0::/user.slice/user-1000.slice/session-3.scope\n"),
+            Some("/user.slice/user-1000.slice/session-3.scope")
+        );
+        assert_eq!(
+            parse_cgroup("12:pids:/\n1:name=systemd:/init.scope\n"),
+            None
+        );
+
+        assert!(is_session_cgroup(
+            "/user.slice/user-1000.slice/session-3.scope"
+        ));
+        assert!(is_session_cgroup("/lemurs/session-tty2"));
+        assert!(!is_session_cgroup("/system.slice/lemurs.service"));
+        assert!(!is_session_cgroup("/"));
+    }
+}
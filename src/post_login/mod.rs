@@ -1,8 +1,10 @@
 use log::{error, info, warn};
+use std::collections::HashSet;
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fmt::Display;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use std::os::unix::process::CommandExt;
 use std::process::{Child, Command, Stdio};
@@ -10,24 +12,69 @@ use std::process::{Child, Command, Stdio};
 use crate::auth::AuthUserInfo;
 use crate::config::{Config, ShellLoginFlag};
 use crate::env_container::EnvironmentContainer;
-use crate::post_login::x::setup_x;
+use crate::post_login::x::{select_display, setup_x, XServer};
 
 use nix::unistd::{Gid, Uid};
+use uzers::os::unix::UserExt;
 
+use self::cgroup::SessionCgroup;
+use self::desktop_entry::DesktopEntry;
+use self::session_limits::SessionLimits;
 use self::wait_with_log::LemursChild;
 use self::x::XSetupError;
 
+pub mod cgroup;
+mod desktop_entry;
 pub(crate) mod env_variables;
+mod logout;
+mod session_limits;
 mod wait_with_log;
 mod x;
+mod xauth;
+
+/// The script that the system shell uses to execute its arguments as a command
+const SHELL_EXEC_SCRIPT: &str = r#"exec "$@""#;
+
+/// The value of `XDG_DATA_DIRS` when it is not set, as given by the XDG Base Directory
+/// Specification
+const DEFAULT_XDG_DATA_DIRS: &str = "/usr/local/share:/usr/share";
 
 #[derive(Debug, Clone)]
 pub enum PostLoginEnvironment {
-    X { xinitrc_path: String },
-    Wayland { script_path: String },
+    X {
+        /// The command that starts the session, which is passed to the xsetup script
+        command: Vec<String>,
+        desktop: DesktopInfo,
+    },
+    Wayland {
+        /// The command that starts the compositor
+        command: Vec<String>,
+        desktop: DesktopInfo,
+    },
     Shell,
 }
 
+/// Information used to tell the session which desktop it is running
+#[derive(Debug, Clone)]
+pub struct DesktopInfo {
+    /// The desktop file id (e.g. `plasma` for `plasma.desktop`) or the file name of the lemurs
+    /// script
+    pub id: String,
+    /// The `DesktopNames` of the desktop entry
+    pub desktop_names: Vec<String>,
+}
+
+impl DesktopInfo {
+    fn from_path(path: &Path, desktop_names: Vec<String>) -> Self {
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Self { id, desktop_names }
+    }
+}
+
 impl PostLoginEnvironment {
     pub fn to_xdg_type(&self) -> &'static str {
         match self {
@@ -37,10 +84,53 @@ impl PostLoginEnvironment {
         }
     }
 
-    // pub fn to_xdg_desktop(&self) -> &str {
-    //     // TODO: Implement properly
-    //     ""
-    // }
+    pub fn desktop_info(&self) -> Option<&DesktopInfo> {
+        match self {
+            Self::X { desktop, .. } | Self::Wayland { desktop, .. } => Some(desktop),
+            Self::Shell => None,
+        }
+    }
+
+    /// Select the X display for the session. This is `None` for sessions that do not run on X11.
+    pub fn select_display(&self, config: &Config) -> Result<Option<String>, EnvironmentStartError> {
+        match self {
+            Self::X { .. } => select_display(config)
+                .map(Some)
+                .map_err(EnvironmentStartError::XSetup),
+            Self::Wayland { .. } | Self::Shell => Ok(None),
+        }
+    }
+
+    /// The wrapper command that is put in front of the session command
+    fn wrapper<'a>(&self, config: &'a Config) -> &'a [String] {
+        let wrappers = &config.session_wrappers;
+
+        let Some(desktop) = self.desktop_info() else {
+            return &[];
+        };
+
+        if let Some(desktop_wrapper) = wrappers.desktops.0.iter().find(|w| w.id == desktop.id) {
+            return &desktop_wrapper.wrapper;
+        }
+
+        match self {
+            Self::X { .. } => &wrappers.x11,
+            Self::Wayland { .. } => &wrappers.wayland,
+            Self::Shell => &[],
+        }
+    }
+
+    /// The value for `XDG_CURRENT_DESKTOP`. This falls back to the desktop id if the desktop
+    /// entry has no `DesktopNames`.
+    pub fn to_xdg_desktop(&self) -> Option<String> {
+        let desktop = self.desktop_info()?;
+
+        if desktop.desktop_names.is_empty() {
+            Some(desktop.id.clone())
+        } else {
+            Some(desktop.desktop_names.join(":"))
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,10 +159,29 @@ impl From<XSetupError> for EnvironmentStartError {
     }
 }
 
+/// Whether lemurs runs with root privileges. Without them, lemurs can only run in preview mode.
+fn has_root_privileges() -> bool {
+    nix::unistd::geteuid().is_root()
+}
+
+/// Run the command with the privileges of the user. `limits` are applied before the privileges are
+/// lowered, so they may also raise limits.
 fn lower_command_permissions_to_user(
     mut command: Command,
-    user_info: &AuthUserInfo<'_>,
+    user_info: &AuthUserInfo,
+    limits: Option<SessionLimits>,
 ) -> Command {
+    // The privileges cannot be changed without root privileges. Then, the command keeps the
+    // privileges of lemurs. This happens in preview mode.
+    if !has_root_privileges() {
+        if let Some(limits) = limits {
+            warn!("Lemurs does not have root privileges. Only applying the session limits that do not need them");
+            apply_unprivileged_limits(&mut command, limits);
+        }
+
+        return command;
+    }
+
     let uid = user_info.uid;
     let gid = user_info.primary_gid;
     let groups = user_info
@@ -84,6 +193,10 @@ fn lower_command_permissions_to_user(
 
     unsafe {
         command.pre_exec(move || {
+            if let Some(limits) = &limits {
+                limits.apply()?;
+            }
+
             // NOTE: The order here is very vital, otherwise permission errors occur
             // This is basically a copy of how the nightly standard library does it.
             nix::unistd::setgroups(&groups)
@@ -96,9 +209,36 @@ fn lower_command_permissions_to_user(
     command
 }
 
+/// Apply the limits that do not need root privileges to the command, which keeps the privileges of
+/// lemurs
+fn apply_unprivileged_limits(command: &mut Command, limits: SessionLimits) {
+    unsafe {
+        command.pre_exec(move || limits.apply_unprivileged());
+    }
+}
+
+/// Start the command in a new session, so that all processes of the environment can be found
+/// when it ends. With `take_terminal`, the terminal on standard input becomes the controlling
+/// terminal of the new session. This needs to happen before the privileges are lowered.
+fn start_new_session(command: &mut Command, take_terminal: bool) {
+    unsafe {
+        command.pre_exec(move || {
+            nix::unistd::setsid()?;
+
+            // Without a controlling terminal, the shell only loses job control. Therefore, a
+            // failure is ignored.
+            if take_terminal {
+                libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 1);
+            }
+
+            Ok(())
+        });
+    }
+}
+
 pub enum SpawnedEnvironment {
     X11 {
-        server: LemursChild,
+        server: XServer,
         client: LemursChild,
     },
     Wayland(LemursChild),
@@ -113,9 +253,13 @@ impl SpawnedEnvironment {
         }
     }
 
-    pub fn wait(self) {
+    /// Wait for the environment to exit. `uid` is the user of the session.
+    pub fn wait(self, config: &Config, uid: libc::uid_t) {
         info!("Waiting for client to exit");
 
+        // The client is the leader of the session of the environment
+        let session = logout::Session::new(self.pid() as libc::pid_t, uid, &config.logout);
+
         match self {
             Self::X11 {
                 mut client,
@@ -128,36 +272,51 @@ impl SpawnedEnvironment {
                     }
                 };
 
+                // The X clients of the session should exit before the X server is gone
+                logout::end_session(&session, &config.logout);
+
                 info!("Telling X server to shut down");
-                match server.send_sigterm() {
+                match server.process.send_sigterm() {
                     Ok(_) => {}
                     Err(err) => error!("Failed to terminate X11. Reason: {err}"),
                 }
 
                 info!("Waiting for X server");
-                match server.wait() {
+                match server.process.wait() {
                     Ok(_) => {}
                     Err(err) => error!("Failed to wait for X11. Reason: {err}"),
                 }
             }
-            Self::Wayland(mut client) => match client.wait() {
-                Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
-                Err(err) => error!("Failed to wait for client. Reason: {err}"),
-            },
-            Self::Tty(mut client) => match client.wait() {
-                Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
-                Err(err) => error!("Failed to wait for client. Reason: {err}"),
-            },
+            Self::Wayland(mut client) => {
+                match client.wait() {
+                    Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
+                    Err(err) => error!("Failed to wait for client. Reason: {err}"),
+                }
+
+                logout::end_session(&session, &config.logout);
+            }
+            Self::Tty(mut client) => {
+                match client.wait() {
+                    Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
+                    Err(err) => error!("Failed to wait for client. Reason: {err}"),
+                }
+
+                logout::end_session(&session, &config.logout);
+            }
         }
     }
 }
 
 impl PostLoginEnvironment {
+    /// Start the environment for the user. In `preview` mode, the environment keeps the
+    /// privileges of lemurs.
     pub fn spawn(
         &self,
-        user_info: &AuthUserInfo<'_>,
+        user_info: &AuthUserInfo,
         process_env: &mut EnvironmentContainer,
         config: &Config,
+        cgroup: Option<&SessionCgroup>,
+        preview: bool,
     ) -> Result<SpawnedEnvironment, EnvironmentStartError> {
         let shell_login_flag = match config.shell_login_flag {
             ShellLoginFlag::None => None,
@@ -165,8 +324,25 @@ impl PostLoginEnvironment {
             ShellLoginFlag::Long => Some("--login"),
         };
 
-        let mut client =
-            lower_command_permissions_to_user(Command::new(&config.system_shell), user_info);
+        let mut client = Command::new(&config.system_shell);
+        start_new_session(&mut client, matches!(self, PostLoginEnvironment::Shell));
+        if let Some(cgroup) = cgroup {
+            cgroup.add_command(&mut client);
+        }
+        let limits = SessionLimits::from_config(
+            config,
+            match self {
+                PostLoginEnvironment::X { .. } => &config.session.x11,
+                PostLoginEnvironment::Wayland { .. } => &config.session.wayland,
+                PostLoginEnvironment::Shell => &config.session.tty,
+            },
+        );
+        let mut client = if preview {
+            apply_unprivileged_limits(&mut client, limits);
+            client
+        } else {
+            lower_command_permissions_to_user(client, user_info, Some(limits))
+        };
 
         let log_path = config.do_log.then_some(Path::new(&config.client_log_path));
 
@@ -174,16 +350,21 @@ impl PostLoginEnvironment {
             client.arg(shell_login_flag);
         }
 
-        client.arg("-c");
+        // The command is passed as arguments to the shell script, so that the shell does not
+        // interpret it. The `lemurs` argument becomes `$0`.
+        client.arg("-c").arg(SHELL_EXEC_SCRIPT).arg("lemurs");
 
         match self {
-            PostLoginEnvironment::X { xinitrc_path } => {
+            PostLoginEnvironment::X { command, .. } => {
                 info!("Starting X11 session");
 
-                let server = setup_x(process_env, user_info, config)
+                let server = setup_x(process_env, user_info, config, preview)
                     .map_err(EnvironmentStartError::XSetup)?;
 
-                client.arg(format!("{} {}", &config.x11.xsetup_path, xinitrc_path));
+                client
+                    .arg(&config.x11.xsetup_path)
+                    .args(self.wrapper(config))
+                    .args(command);
 
                 let client = match LemursChild::spawn(client, log_path) {
                     Ok(child) => child,
@@ -195,10 +376,10 @@ impl PostLoginEnvironment {
 
                 Ok(SpawnedEnvironment::X11 { server, client })
             }
-            PostLoginEnvironment::Wayland { script_path } => {
+            PostLoginEnvironment::Wayland { command, .. } => {
                 info!("Starting Wayland session");
 
-                client.arg(script_path);
+                client.args(self.wrapper(config)).args(command);
 
                 let child = match LemursChild::spawn(client, log_path) {
                     Ok(child) => child,
@@ -234,107 +415,240 @@ impl PostLoginEnvironment {
     }
 }
 
-fn parse_desktop_entry(path: &Path, _: &Config) -> Result<(String, String), String> {
-    let content = match fs::read_to_string(path) {
-        Ok(content) => content,
-        Err(err) => {
-            return Err(format!("file cannot be read. Reason: {err}"));
-        }
-    };
-
-    let desktop_entry = match deentry::DesktopEntry::try_from(&content[..]) {
-        Ok(v) => v,
-        Err(err) => {
-            return Err(format!("file cannot be parsed. Reason: {err}"));
-        }
-    };
+/// The locale used to localize the names of desktop entries
+fn desktop_entry_locale(config: &Config) -> String {
+    if !config.environment_switcher.locale.is_empty() {
+        return config.environment_switcher.locale.clone();
+    }
 
-    let Some(desktop_entry) = desktop_entry
-        .groups()
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
         .iter()
-        .find(|g| g.name() == "Desktop Entry")
-    else {
-        return Err("file does not contain 'Desktop Entry' group".to_string());
-    };
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
 
-    let Some(exec) = desktop_entry.get("Exec") else {
-        return Err("'Exec' key cannot be found".to_string());
+fn parse_desktop_entry(
+    path: &Path,
+    owner: Option<libc::uid_t>,
+    config: &Config,
+) -> Result<(String, Vec<String>, DesktopInfo), String> {
+    let locale = desktop_entry_locale(config);
+    let desktop_entry = match owner {
+        Some(uid) => DesktopEntry::from_user_file(path, &locale, uid)?,
+        None => DesktopEntry::from_file(path, &locale)?,
     };
 
-    let exec = match exec.value().as_string() {
-        Ok(v) => v,
-        Err(err) => {
-            return Err(format!(
-                "'Exec' key does not contain a string. Reason: {err}"
-            ));
-        }
+    if desktop_entry.hidden {
+        return Err("it is marked as hidden".to_string());
+    }
+
+    if desktop_entry.no_display {
+        return Err("it is marked to not be displayed".to_string());
+    }
+
+    if !desktop_entry.is_installed(&config.initial_path) {
+        return Err(format!(
+            "'{}' from 'TryExec' is not installed",
+            desktop_entry.try_exec.unwrap_or_default()
+        ));
+    }
+
+    if let Some(comment) = &desktop_entry.comment {
+        info!("Found '{}': {comment}", path.display());
+    }
+
+    let name = desktop_entry
+        .name
+        .unwrap_or_else(|| desktop_entry.exec.join(" "));
+
+    Ok((
+        name,
+        desktop_entry.exec,
+        DesktopInfo::from_path(path, desktop_entry.desktop_names),
+    ))
+}
+
+/// A user whose own sessions are offered next to the system-wide sessions
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionUser {
+    pub username: String,
+    uid: libc::uid_t,
+    home_dir: PathBuf,
+}
+
+impl SessionUser {
+    pub fn from_username(username: &str) -> Option<Self> {
+        let user = uzers::get_user_by_name(username)?;
+
+        Some(Self {
+            username: username.to_string(),
+            uid: user.uid(),
+            home_dir: user.home_dir().to_path_buf(),
+        })
+    }
+
+    fn session_dirs(&self, paths: &[String]) -> Vec<SessionDir> {
+        paths
+            .iter()
+            .map(|path| SessionDir {
+                path: self.home_dir.join(path.trim_start_matches('/')),
+                owner: Some(self.uid),
+            })
+            .collect()
+    }
+}
+
+/// A directory with session desktop entries
+struct SessionDir {
+    path: PathBuf,
+    /// The user that owns the directory, if it is a directory in the home of a user
+    owner: Option<libc::uid_t>,
+}
+
+/// The system-wide directories to search for session desktop entries. If none are configured,
+/// this is the `subdir` of every directory in `XDG_DATA_DIRS`. A `legacy` directory from a
+/// deprecated option is searched first.
+fn session_dirs(configured: &[String], legacy: &str, subdir: &str) -> Vec<SessionDir> {
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|data_dirs| !data_dirs.is_empty())
+        .unwrap_or_else(|| DEFAULT_XDG_DATA_DIRS.to_string());
+
+    session_dir_paths(configured, legacy, subdir, &data_dirs)
+        .into_iter()
+        .map(|path| SessionDir { path, owner: None })
+        .collect()
+}
+
+fn session_dir_paths(
+    configured: &[String],
+    legacy: &str,
+    subdir: &str,
+    data_dirs: &str,
+) -> Vec<PathBuf> {
+    let paths: Vec<PathBuf> = if configured.is_empty() {
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join(subdir))
+            .collect()
+    } else {
+        configured.iter().map(PathBuf::from).collect()
     };
 
-    let name = match desktop_entry.get("Name") {
-        Some(name) => match name.value().as_string() {
-            Ok(v) => v,
+    (!legacy.is_empty())
+        .then(|| PathBuf::from(legacy))
+        .into_iter()
+        .chain(paths)
+        .collect()
+}
+
+/// Find the desktop entries in the session directories. When multiple directories contain a
+/// desktop entry with the same file id, only the one from the earliest directory is used.
+fn find_desktop_entries(dirs: Vec<SessionDir>) -> Vec<(PathBuf, Option<libc::uid_t>)> {
+    let mut seen_ids = HashSet::new();
+    let mut entries = Vec::new();
+
+    for dir in dirs {
+        let paths = match fs::read_dir(&dir.path) {
+            Ok(paths) => paths,
             Err(err) => {
-                warn!(
-                    "Cannot use 'Name' in '{}' because it does not contain a string. Reason: {err}",
-                    path.display()
+                info!(
+                    "Skipping sessions folder '{}'. Reason: {err}",
+                    dir.path.display()
                 );
-
-                exec
+                continue;
             }
-        },
-        None => exec,
-    };
+        };
 
-    Ok((name.to_string(), exec.to_string()))
-}
+        for path in paths {
+            let Ok(path) = path else {
+                continue;
+            };
 
-pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
-    // NOTE: Maybe we can do something smart with `with_capacity` here.
-    let mut envs = Vec::new();
+            let path = path.path();
 
-    match fs::read_dir(&config.x11.xsessions_path) {
-        Ok(paths) => {
-            for path in paths {
-                let Ok(path) = path else {
-                    continue;
-                };
+            if path.extension() != Some(OsStr::new("desktop")) {
+                continue;
+            }
 
-                let path = path.path();
+            let Some(file_id) = path.file_name().map(|name| name.to_os_string()) else {
+                continue;
+            };
 
-                match parse_desktop_entry(&path, config) {
-                    Ok((name, exec)) => {
-                        info!("Added environment '{name}' from xsessions");
-                        envs.push((name, PostLoginEnvironment::X { xinitrc_path: exec }));
-                    }
-                    Err(err) => warn!("Skipping '{}', because {err}", path.display()),
-                }
+            if seen_ids.insert(file_id) {
+                entries.push((path, dir.owner));
+            } else {
+                info!(
+                    "Skipping '{}', because it is overridden by an earlier directory",
+                    path.display()
+                );
             }
         }
-        Err(err) => {
-            warn!("Failed to read from the xsessions folder '{err}'",);
-        }
     }
 
-    match fs::read_dir(&config.wayland.wayland_sessions_path) {
-        Ok(paths) => {
-            for path in paths {
-                let Ok(path) = path else {
-                    continue;
-                };
+    entries
+}
 
-                let path = path.path();
+/// Get all available environments. If a user is given and user sessions are enabled, the
+/// sessions from the home directory of that user are included.
+pub fn get_envs(
+    config: &Config,
+    user: Option<&SessionUser>,
+) -> Vec<(String, PostLoginEnvironment)> {
+    // NOTE: Maybe we can do something smart with `with_capacity` here.
+    let mut envs = Vec::new();
 
-                match parse_desktop_entry(&path, config) {
-                    Ok((name, exec)) => {
-                        info!("Added environment '{name}' from wayland sessions");
-                        envs.push((name, PostLoginEnvironment::Wayland { script_path: exec }))
-                    }
-                    Err(err) => warn!("Skipping '{}', because {err}", path.display()),
-                }
+    let user = user.filter(|_| config.user_sessions.enabled);
+
+    let mut xsessions_dirs = user
+        .map(|user| user.session_dirs(&config.user_sessions.xsessions_paths))
+        .unwrap_or_default();
+    xsessions_dirs.extend(session_dirs(
+        &config.x11.xsessions_paths,
+        &config.x11.xsessions_path,
+        "xsessions",
+    ));
+
+    let mut wayland_sessions_dirs = user
+        .map(|user| user.session_dirs(&config.user_sessions.wayland_sessions_paths))
+        .unwrap_or_default();
+    wayland_sessions_dirs.extend(session_dirs(
+        &config.wayland.wayland_sessions_paths,
+        &config.wayland.wayland_sessions_path,
+        "wayland-sessions",
+    ));
+
+    for (path, owner) in find_desktop_entries(xsessions_dirs) {
+        match parse_desktop_entry(&path, owner, config) {
+            Ok((name, exec, desktop)) => {
+                info!("Added environment '{name}' from xsessions");
+                envs.push((
+                    name,
+                    PostLoginEnvironment::X {
+                        command: exec,
+                        desktop,
+                    },
+                ));
             }
+            Err(err) => warn!("Skipping '{}', because {err}", path.display()),
         }
-        Err(err) => {
-            warn!("Failed to read from the wayland sessions folder '{err}'",);
+    }
+
+    for (path, owner) in find_desktop_entries(wayland_sessions_dirs) {
+        match parse_desktop_entry(&path, owner, config) {
+            Ok((name, exec, desktop)) => {
+                info!("Added environment '{name}' from wayland sessions");
+                envs.push((
+                    name,
+                    PostLoginEnvironment::Wayland {
+                        command: exec,
+                        desktop,
+                    },
+                ))
+            }
+            Err(err) => warn!("Skipping '{}', because {err}", path.display()),
         }
     }
 
@@ -357,10 +671,10 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
 
                         info!("Added environment '{file_name}' from lemurs x11 scripts");
                         envs.push((
-                            file_name,
+                            file_name.clone(),
                             PostLoginEnvironment::X {
-                                xinitrc_path: match path.path().to_str() {
-                                    Some(p) => p.to_string(),
+                                command: match path.path().to_str() {
+                                    Some(p) => vec![p.to_string()],
                                     None => {
                                         warn!(
                                     "Skipped item because it was impossible to convert to string"
@@ -368,6 +682,10 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                                         continue;
                                     }
                                 },
+                                desktop: DesktopInfo {
+                                    id: file_name,
+                                    desktop_names: Vec::new(),
+                                },
                             },
                         ));
                     } else {
@@ -406,10 +724,10 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
 
                         info!("Added environment '{file_name}' from lemurs wayland scripts");
                         envs.push((
-                            file_name,
+                            file_name.clone(),
                             PostLoginEnvironment::Wayland {
-                                script_path: match path.path().to_str() {
-                                    Some(p) => p.to_string(),
+                                command: match path.path().to_str() {
+                                    Some(p) => vec![p.to_string()],
                                     None => {
                                         warn!(
                                     "Skipped item because it was impossible to convert to string"
@@ -417,6 +735,10 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                                         continue;
                                     }
                                 },
+                                desktop: DesktopInfo {
+                                    id: file_name,
+                                    desktop_names: Vec::new(),
+                                },
                             },
                         ));
                     } else {
@@ -445,3 +767,45 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
 
     envs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_session_dir() {
+        let data_dirs = "/usr/local/share:/usr/share";
+
+        // Without configured directories, `XDG_DATA_DIRS` is still searched after the legacy
+        // directory
+        assert_eq!(
+            session_dir_paths(&[], "/opt/xsessions", "xsessions", data_dirs),
+            [
+                PathBuf::from("/opt/xsessions"),
+                PathBuf::from("/usr/local/share/xsessions"),
+                PathBuf::from("/usr/share/xsessions"),
+            ]
+        );
+
+        assert_eq!(
+            session_dir_paths(
+                &["/etc/xsessions".to_string()],
+                "/opt/xsessions",
+                "xsessions",
+                data_dirs
+            ),
+            [
+                PathBuf::from("/opt/xsessions"),
+                PathBuf::from("/etc/xsessions"),
+            ]
+        );
+
+        assert_eq!(
+            session_dir_paths(&[], "", "xsessions", data_dirs),
+            [
+                PathBuf::from("/usr/local/share/xsessions"),
+                PathBuf::from("/usr/share/xsessions"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Resource limits, the umask and the nice level of sessions.
+//!
+//! These are read from the `[session]` section of the configuration, where every session type can
+//! override the values. Empty values leave the setting of lemurs unchanged, except for the umask,
+//! which defaults to `UMASK` in `/etc/login.defs`.
+
+use std::fs::read_to_string;
+use std::io;
+
+use log::warn;
+
+use crate::config::{Config, SessionLimitsConfig};
+
+const LOGIN_DEFS_PATH: &str = "/etc/login.defs";
+
+/// A soft and a hard resource limit
+type Rlimit = (libc::rlim_t, libc::rlim_t);
+
+/// The limits that are applied to a session before the privileges are lowered
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionLimits {
+    nofile: Option<Rlimit>,
+    nproc: Option<Rlimit>,
+    core: Option<Rlimit>,
+    umask: Option<libc::mode_t>,
+    nice: Option<libc::c_int>,
+}
+
+fn parse_rlimit_value(value: &str) -> Option<libc::rlim_t> {
+    match value.trim() {
+        "unlimited" | "infinity" => Some(libc::RLIM_INFINITY),
+        value => value.parse().ok(),
+    }
+}
+
+/// Parse a resource limit. A single value sets both the soft and the hard limit. Otherwise, the
+/// limits are given as `soft:hard`. The soft limit cannot be higher than the hard limit.
+fn parse_rlimit(value: &str) -> Option<Rlimit> {
+    match value.split_once(':') {
+        Some((soft, hard)) => Some((parse_rlimit_value(soft)?, parse_rlimit_value(hard)?))
+            .filter(|(soft, hard)| soft <= hard),
+        None => parse_rlimit_value(value).map(|limit| (limit, limit)),
+    }
+}
+
+fn parse_umask(value: &str) -> Option<libc::mode_t> {
+    libc::mode_t::from_str_radix(value.trim(), 8)
+        .ok()
+        .filter(|umask| *umask <= 0o777)
+}
+
+/// Get the `UMASK` value from the contents of `/etc/login.defs`
+fn login_defs_umask(login_defs: &str) -> Option<libc::mode_t> {
+    login_defs
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next()? == "UMASK").then(|| fields.next()).flatten()
+        })
+        .and_then(parse_umask)
+}
+
+/// Parse a configured value. Invalid values are ignored with a warning.
+fn parse_setting<T>(name: &str, value: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+    if value.is_empty() {
+        return None;
+    }
+
+    let parsed = parse(value);
+    if parsed.is_none() {
+        warn!("Invalid value `{value}` for `session.{name}`. Ignoring it");
+    }
+
+    parsed
+}
+
+impl SessionLimits {
+    /// Determine the limits of a session type from the configuration. `overrides` are the values
+    /// for the session type, which take precedence over the values for all sessions.
+    pub fn from_config<'a>(config: &'a Config, overrides: &'a SessionLimitsConfig) -> Self {
+        let session = &config.session;
+        let pick = |base: &'a str, override_value: &'a str| {
+            if override_value.is_empty() {
+                base
+            } else {
+                override_value
+            }
+        };
+
+        let umask = parse_setting("umask", pick(&session.umask, &overrides.umask), parse_umask)
+            .or_else(|| {
+                read_to_string(LOGIN_DEFS_PATH)
+                    .ok()
+                    .and_then(|login_defs| login_defs_umask(&login_defs))
+            });
+
+        Self {
+            nofile: parse_setting(
+                "nofile",
+                pick(&session.nofile, &overrides.nofile),
+                parse_rlimit,
+            ),
+            nproc: parse_setting(
+                "nproc",
+                pick(&session.nproc, &overrides.nproc),
+                parse_rlimit,
+            ),
+            core: parse_setting("core", pick(&session.core, &overrides.core), parse_rlimit),
+            umask,
+            nice: parse_setting("nice", pick(&session.nice, &overrides.nice), |value| {
+                value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|nice| (-20..=19).contains(nice))
+            }),
+        }
+    }
+
+    /// Apply the limits to the current process. This is called between `fork` and `exec`, and
+    /// thus it only makes system calls.
+    pub fn apply(&self) -> io::Result<()> {
+        self.apply_limits(true)
+    }
+
+    /// Apply the limits that do not need root privileges to the current process. Resource limits
+    /// are only lowered and a nice level that raises the priority is skipped. Like
+    /// [`SessionLimits::apply`], this only makes system calls.
+    pub fn apply_unprivileged(&self) -> io::Result<()> {
+        self.apply_limits(false)
+    }
+
+    fn apply_limits(&self, privileged: bool) -> io::Result<()> {
+        let resources = [
+            (libc::RLIMIT_NOFILE, self.nofile),
+            (libc::RLIMIT_NPROC, self.nproc),
+            (libc::RLIMIT_CORE, self.core),
+        ];
+
+        for (resource, limit) in resources {
+            let Some((mut soft, mut hard)) = limit else {
+                continue;
+            };
+
+            // Without privileges, the hard limit cannot be raised
+            if !privileged {
+                let mut current = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if unsafe { libc::getrlimit(resource, &mut current) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                hard = hard.min(current.rlim_max);
+                soft = soft.min(hard);
+            }
+
+            let rlimit = libc::rlimit {
+                rlim_cur: soft,
+                rlim_max: hard,
+            };
+            if unsafe { libc::setrlimit(resource, &rlimit) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        if let Some(umask) = self.umask {
+            unsafe { libc::umask(umask) };
+        }
+
+        if let Some(nice) = self.nice {
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+                let err = io::Error::last_os_error();
+
+                // Without privileges, the priority cannot be raised
+                if privileged || err.raw_os_error() != Some(libc::EACCES) {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rlimits() {
+        assert_eq!(parse_rlimit("1024"), Some((1024, 1024)));
+        assert_eq!(parse_rlimit("1024:4096"), Some((1024, 4096)));
+        assert_eq!(parse_rlimit("0:unlimited"), Some((0, libc::RLIM_INFINITY)));
+        assert_eq!(parse_rlimit("many"), None);
+        assert_eq!(parse_rlimit("1024:"), None);
+        assert_eq!(parse_rlimit("4096:1024"), None);
+        assert_eq!(parse_rlimit("unlimited:1024"), None);
+    }
+
+    #[test]
+    fn umask_from_login_defs() {
+        let login_defs = "# UMASK 077\nUSERGROUPS_ENAB yes\n  UMASK\t\t027\nUMASK 022\n";
+        assert_eq!(login_defs_umask(login_defs), Some(0o027));
+        assert_eq!(login_defs_umask("UMASK\n"), None);
+        assert_eq!(login_defs_umask("UMASK 999"), None);
+    }
+
+    #[test]
+    fn unprivileged_limits() {
+        use std::os::unix::process::CommandExt;
+        use std::process::Command;
+
+        let limits = SessionLimits {
+            nofile: Some((64, 128)),
+            nproc: None,
+            core: None,
+            umask: Some(0o027),
+            nice: None,
+        };
+
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", "ulimit -Sn; umask"]);
+        unsafe {
+            command.pre_exec(move || limits.apply_unprivileged());
+        }
+
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "64\n0027\n");
+    }
+
+    #[test]
+    fn session_type_overrides() {
+        let mut config = Config::default();
+        config.session.nofile = "1024".to_string();
+        config.session.nproc = "512".to_string();
+        config.session.umask = "077".to_string();
+        config.session.x11.nofile = "4096".to_string();
+        config.session.x11.nice = "5".to_string();
+
+        let limits = SessionLimits::from_config(&config, &config.session.x11);
+        assert_eq!(
+            limits,
+            SessionLimits {
+                nofile: Some((4096, 4096)),
+                nproc: Some((512, 512)),
+                core: None,
+                umask: Some(0o077),
+                nice: Some(5),
+            }
+        );
+    }
+}
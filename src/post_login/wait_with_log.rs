@@ -81,10 +81,20 @@ impl LemursChild {
                     .stderr(Stdio::null())
                     .spawn()?,
             ),
-            Some(log_path) => Self::Log(LimitedOutputChild::spawn(command, log_path)?),
+            Some(log_path) => Self::Log(LimitedOutputChild::spawn(command, log_path, false)?),
         })
     }
 
+    /// Spawn a process that appends its output to the log file instead of overwriting it
+    pub fn spawn_appending(command: Command, log_path: Option<&Path>) -> io::Result<Self> {
+        match log_path {
+            None => Self::spawn(command, None),
+            Some(log_path) => Ok(Self::Log(LimitedOutputChild::spawn(
+                command, log_path, true,
+            )?)),
+        }
+    }
+
     pub fn wait(&mut self) -> io::Result<ExitStatus> {
         match self {
             Self::NoLog(process) => process.wait(),
@@ -121,7 +131,7 @@ impl LemursChild {
 }
 
 impl LimitedOutputChild {
-    pub fn spawn(mut command: Command, log_path: &Path) -> io::Result<Self> {
+    pub fn spawn(mut command: Command, log_path: &Path, append: bool) -> io::Result<Self> {
         const STDOUT_PIPE_RECV: Token = Token(0);
         const STDERR_PIPE_RECV: Token = Token(1);
         const WAKER_TOKEN: Token = Token(2);
@@ -138,12 +148,15 @@ impl LimitedOutputChild {
 
         let mut file_options = OpenOptions::new();
         file_options.create(true);
-        file_options.write(true);
-        file_options.truncate(true);
+        // Other processes may append to the same log file while this process runs
+        file_options.append(true);
 
         let mut process = command.spawn()?;
 
         let file = file_options.open(log_path)?;
+        if !append {
+            file.set_len(0)?;
+        }
 
         let Some(stdout) = process.stdout.take() else {
             return Err(io::Error::other("Failed to grab stdout"));
@@ -1,27 +1,42 @@
 use libc::{signal, SIGUSR1, SIG_DFL, SIG_IGN};
-use rand::Rng;
 
 use once_cell::sync::Lazy;
 
 use std::env;
 use std::error::Error;
 use std::fmt::Display;
-use std::fs::remove_file;
-use std::process::{Command, Stdio};
+use std::fs::{create_dir_all, read_to_string, remove_file};
+use std::io::{self, ErrorKind, Read};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
 use std::sync::atomic::AtomicBool;
-use std::{thread, time};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mio::unix::pipe::{self, Receiver, Sender};
+use mio::{Events, Interest, Poll, Token};
 
 use std::path::{Path, PathBuf};
 
-use log::{error, info};
+use log::{error, info, warn};
 
 use crate::auth::AuthUserInfo;
 use crate::config::Config;
 use crate::env_container::EnvironmentContainer;
 use crate::post_login::wait_with_log::LemursChild;
+use crate::post_login::{has_root_privileges, lower_command_permissions_to_user, xauth};
+
+use nix::unistd::{chown, Uid};
 
 const XSTART_CHECK_INTERVAL_MILLIS: u64 = 100;
 
+/// The value of `x11_display` that selects the first free display
+const AUTO_DISPLAY: &str = "auto";
+/// The number of displays that are tried when selecting a free display
+const MAX_DISPLAYS: u32 = 64;
+
 #[derive(Debug, Clone)]
 pub enum XSetupError {
     DisplayEnvVar,
@@ -32,6 +47,10 @@ pub enum XSetupError {
     XServerStart,
     XServerTimeout,
     XServerPrematureExit,
+    InvalidDisplay(String),
+    DisplayInUse(String),
+    NoFreeDisplay,
+    DisplayMismatch(u32),
 }
 
 impl Display for XSetupError {
@@ -47,163 +66,727 @@ impl Display for XSetupError {
             Self::XServerPrematureExit => {
                 f.write_str("X server exited before it signaled to accept connections")
             }
+            Self::InvalidDisplay(display) => write!(f, "`{display}` is not a valid X display"),
+            Self::DisplayInUse(display) => {
+                write!(f, "X display `{display}` is used by another X server")
+            }
+            Self::NoFreeDisplay => f.write_str("Failed to find a free X display"),
+            Self::DisplayMismatch(display_number) => write!(
+                f,
+                "X server started on display `:{display_number}` instead of the selected display"
+            ),
         }
     }
 }
 
 impl Error for XSetupError {}
 
-fn mcookie() -> String {
-    // TODO: Verify that this is actually safe. Maybe just use the mcookie binary?? Is that always
-    // available?
-    let mut rng = rand::rng();
-    let cookie: u128 = rng.random();
-    format!("{cookie:032x}")
+fn lock_file_path(display_number: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/.X{display_number}-lock"))
+}
+
+fn socket_path(display_number: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/.X11-unix/X{display_number}"))
+}
+
+fn is_process_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: Sending signal 0 only checks whether the process exists.
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    // EPERM means that the process exists, but we cannot signal it
+    std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// Check whether an X server is using a display. Lock files and sockets that are left behind by
+/// X servers that are no longer running are removed.
+fn is_display_in_use(display_number: u32) -> bool {
+    let lock_file_path = lock_file_path(display_number);
+    let socket_path = socket_path(display_number);
+
+    match read_to_string(&lock_file_path) {
+        Ok(content) => {
+            // The lock file contains the pid of the X server
+            match content.trim().parse::<libc::pid_t>() {
+                Ok(pid) if pid > 0 && is_process_alive(pid) => return true,
+                Ok(pid) => info!(
+                    "Removing stale lock file `{}` of X server with pid {pid}",
+                    lock_file_path.display()
+                ),
+                Err(_) => info!("Removing invalid lock file `{}`", lock_file_path.display()),
+            }
+
+            if let Err(err) = remove_file(&lock_file_path) {
+                warn!(
+                    "Failed to remove stale lock file `{}`. Reason: {err}",
+                    lock_file_path.display()
+                );
+                return true;
+            }
+
+            let _ = remove_file(&socket_path);
+            false
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if !socket_path.exists() {
+                return false;
+            }
+
+            // A socket without a lock file is only in use if something accepts connections
+            if UnixStream::connect(&socket_path).is_ok() {
+                return true;
+            }
+
+            info!("Removing stale X socket `{}`", socket_path.display());
+            remove_file(&socket_path).is_err()
+        }
+        Err(err) => {
+            warn!(
+                "Failed to read lock file `{}`. Reason: {err}",
+                lock_file_path.display()
+            );
+            true
+        }
+    }
+}
+
+/// Get the number of a local display (e.g. `1` for `:1` or `:1.0`)
+fn display_number(display: &str) -> Option<u32> {
+    display
+        .strip_prefix(':')
+        .and_then(|n| n.split('.').next())
+        .and_then(|n| n.parse().ok())
+}
+
+/// Get the path of the Xauthority file of the user
+fn user_xauthority_path(config: &Config) -> Result<PathBuf, XSetupError> {
+    if config.x11.xauthority_in_runtime_dir {
+        match env::var("XDG_RUNTIME_DIR") {
+            Ok(runtime_dir) if Path::new(&runtime_dir).is_dir() => {
+                return Ok(Path::new(&runtime_dir).join("Xauthority"));
+            }
+            _ => warn!("`XDG_RUNTIME_DIR` is not available. Placing Xauthority file in `HOME`"),
+        }
+    }
+
+    let home_dir = env::var("HOME").map_err(|_| XSetupError::HomeEnvVar)?;
+    Ok(Path::new(&home_dir).join(".Xauthority"))
+}
+
+/// Write the Xauthority files for the X server and the user. The file for the X server is only
+/// accessible by root, or by the user with a rootless X server. This returns the path of the file
+/// for the X server.
+fn write_xauthority_files(
+    process_env: &mut EnvironmentContainer,
+    user_info: &AuthUserInfo,
+    config: &Config,
+    display_number: u32,
+    preview: bool,
+) -> Result<PathBuf, XSetupError> {
+    let cookie = xauth::new_cookie();
+
+    let server_auth_dir = Path::new(&config.x11.xserver_auth_dir);
+    if let Err(err) = create_dir_all(server_auth_dir) {
+        error!(
+            "Failed to create directory `{}`. Reason: {err}",
+            server_auth_dir.display()
+        );
+        return Err(XSetupError::FillingXAuth);
+    }
+
+    let server_xauth_path = server_auth_dir.join(format!("X{display_number}.auth"));
+    info!(
+        "Writing X server authorization to `{}`",
+        server_xauth_path.display()
+    );
+    // Without root privileges, files cannot be given to another user. In preview mode, the
+    // session keeps the privileges of lemurs, so the files are not given to the user either.
+    let user_owner =
+        (has_root_privileges() && !preview).then_some((user_info.uid, user_info.primary_gid));
+
+    // A rootless X server needs to read its authorization file
+    let server_owner = user_owner.filter(|_| config.x11.rootless);
+    xauth::write_file(&server_xauth_path, display_number, &cookie, server_owner).map_err(
+        |err| {
+            error!("Failed to write X server authorization file. Reason: {err}");
+            XSetupError::FillingXAuth
+        },
+    )?;
+
+    let user_xauth_path = user_xauthority_path(config)?;
+    info!("Filling Xauthority file at `{}`", user_xauth_path.display());
+    xauth::write_file(&user_xauth_path, display_number, &cookie, user_owner).map_err(|err| {
+        error!("Failed to fill Xauthority file. Reason: {err}");
+        XSetupError::FillingXAuth
+    })?;
+
+    let user_xauth_path = user_xauth_path
+        .to_str()
+        .ok_or(XSetupError::InvalidUTF8Path)?;
+    process_env.set("XAUTHORITY", user_xauth_path);
+
+    Ok(server_xauth_path)
+}
+
+/// Select the X display to use for a session. This is the configured display or, if `auto` is
+/// configured, the first display that is not used by another X server.
+pub fn select_display(config: &Config) -> Result<String, XSetupError> {
+    let configured = config.x11.x11_display.trim();
+
+    if configured == AUTO_DISPLAY {
+        let display_number = (0..MAX_DISPLAYS)
+            .find(|n| !is_display_in_use(*n))
+            .ok_or(XSetupError::NoFreeDisplay)?;
+
+        info!("Selected free X display :{display_number}");
+
+        return Ok(format!(":{display_number}"));
+    }
+
+    let display_number = display_number(configured)
+        .ok_or_else(|| XSetupError::InvalidDisplay(configured.to_string()))?;
+
+    if is_display_in_use(display_number) {
+        return Err(XSetupError::DisplayInUse(configured.to_string()));
+    }
+
+    Ok(configured.to_string())
 }
 
 static X_HAS_STARTED: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
 
 #[allow(dead_code)]
-fn handle_sigusr1(_: i32) {
+extern "C" fn handle_sigusr1(_: i32) {
     X_HAS_STARTED.store(true, std::sync::atomic::Ordering::SeqCst);
 
     unsafe {
-        signal(SIGUSR1, handle_sigusr1 as usize);
+        signal(
+            SIGUSR1,
+            handle_sigusr1 as extern "C" fn(i32) as libc::sighandler_t,
+        );
+    }
+}
+
+/// A running X server
+pub struct XServer {
+    pub process: LemursChild,
+    /// Keeps the virtual terminal owned by the user while a rootless X server runs
+    #[allow(dead_code)]
+    vt_ownership: Option<VtOwnership>,
+}
+
+/// Gives a user ownership of a virtual terminal, so that a rootless X server can open it. The
+/// ownership is given back to root when this is dropped.
+struct VtOwnership {
+    path: PathBuf,
+}
+
+impl VtOwnership {
+    fn give(vtnr: &str, uid: libc::uid_t) -> nix::Result<Self> {
+        let path = PathBuf::from(format!("/dev/tty{vtnr}"));
+        chown(&path, Some(Uid::from_raw(uid)), None)?;
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for VtOwnership {
+    fn drop(&mut self) {
+        if let Err(err) = chown(&self.path, Some(Uid::from_raw(0)), None) {
+            error!(
+                "Failed to give the ownership of `{}` back to root. Reason: {err}",
+                self.path.display()
+            );
+        }
     }
 }
 
+/// Start the X server for a session. In `preview` mode, the X server and its files keep the
+/// privileges of lemurs.
 pub fn setup_x(
     process_env: &mut EnvironmentContainer,
     user_info: &AuthUserInfo,
     config: &Config,
-) -> Result<LemursChild, XSetupError> {
-    use std::os::unix::process::CommandExt;
-
+    preview: bool,
+) -> Result<XServer, XSetupError> {
     info!("Start setup of X server");
 
     let display_value = env::var("DISPLAY").map_err(|_| XSetupError::DisplayEnvVar)?;
-    let vtnr_value = env::var("XDG_VTNR").map_err(|_| XSetupError::VTNREnvVar)?;
 
-    // Setup xauth
-    let xauth_dir = PathBuf::from(env::var("HOME").map_err(|_| XSetupError::HomeEnvVar)?);
-    let xauth_path = xauth_dir.join(".Xauthority");
-
-    info!(
-        "Filling `.Xauthority` file at `{xauth_path}`",
-        xauth_path = xauth_path.display()
-    );
+    let display_number = display_number(&display_value)
+        .ok_or_else(|| XSetupError::InvalidDisplay(display_value.clone()))?;
+    let server_xauth_path =
+        write_xauthority_files(process_env, user_info, config, display_number, preview)?;
+    let server_xauth_path = server_xauth_path
+        .to_str()
+        .ok_or(XSetupError::InvalidUTF8Path)?;
 
-    // Make sure that we are generating a new file. This is necessary sometimes, since there may be
-    // a `root` permission `.Xauthority` file there.
-    let _ = remove_file(&xauth_path);
+    if config.x11.virtual_server {
+        info!("Starting virtual X server");
 
-    Command::new(&config.system_shell)
-        .arg("-c")
-        .arg(format!(
-            "{} add {} . {}",
-            &config.x11.xauth_path,
-            display_value,
-            mcookie()
-        ))
-        .uid(user_info.uid)
-        .gid(user_info.primary_gid)
-        .stdout(Stdio::null()) // TODO: Maybe this should be logged or something?
-        .stderr(Stdio::null()) // TODO: Maybe this should be logged or something?
-        .status()
-        .map_err(|err| {
-            error!(
-                "Failed to fill Xauthority file with `xauth`. Reason: {}",
-                err
-            );
-            XSetupError::FillingXAuth
-        })?;
-
-    let xauth_path = xauth_path.to_str().ok_or(XSetupError::InvalidUTF8Path)?;
-    process_env.set("XAUTHORITY", xauth_path);
+        let args = fill_placeholders(
+            &config.x11.virtual_server_args,
+            &[("%display%", &display_value)],
+        );
+        let process = start_server(
+            None,
+            config,
+            &config.x11.virtual_server_path,
+            args,
+            &display_value,
+            server_xauth_path,
+        )?;
+
+        run_display_setup(config, &display_value, server_xauth_path);
+
+        return Ok(XServer {
+            process,
+            vt_ownership: None,
+        });
+    }
 
+    let vtnr_value = env::var("XDG_VTNR").map_err(|_| XSetupError::VTNREnvVar)?;
     let doubledigit_vtnr = if vtnr_value.len() == 1 {
         format!("0{vtnr_value}")
     } else {
-        vtnr_value
+        vtnr_value.clone()
     };
+    let args = fill_placeholders(
+        &config.x11.xserver_args,
+        &[("%display%", &display_value), ("%vt%", &doubledigit_vtnr)],
+    );
 
-    // Here we explicitely ignore the first USR defined signal. Xorg looks at whether this signal
-    // is ignored or not. If it is ignored, it will send that signal to the parent when it ready to
-    // receive connections. This is also how xinit does it.
-    //
-    // After we spawn the Xorg process, we need to make sure to quickly re-enable this signal as we
-    // need to listen to the signal by Xorg.
-    unsafe {
-        libc::signal(SIGUSR1, SIG_IGN);
-    }
+    let rootless_server = if config.x11.rootless && !preview {
+        start_rootless_server(
+            user_info,
+            config,
+            args.clone(),
+            &display_value,
+            &vtnr_value,
+            server_xauth_path,
+        )
+        .inspect_err(|err| {
+            warn!("Failed to start rootless X server. Starting it as root. Reason: {err}")
+        })
+        .ok()
+    } else {
+        None
+    };
+
+    let server = match rootless_server {
+        Some(server) => server,
+        None => XServer {
+            process: start_server(
+                None,
+                config,
+                &config.x11.xserver_path,
+                args,
+                &display_value,
+                server_xauth_path,
+            )?,
+            vt_ownership: None,
+        },
+    };
+
+    run_display_setup(config, &display_value, server_xauth_path);
+
+    Ok(server)
+}
 
-    let mut child = Command::new(&config.system_shell);
+/// Fill in the placeholders of the configured X server arguments
+fn fill_placeholders(args: &[String], placeholders: &[(&str, &str)]) -> Vec<String> {
+    args.iter()
+        .map(|arg| {
+            placeholders
+                .iter()
+                .fold(arg.clone(), |arg, (placeholder, value)| {
+                    arg.replace(placeholder, value)
+                })
+        })
+        .collect()
+}
+
+/// Run the display setup command as root. This is ran after the X server accepts connections and
+/// before the session starts. Failures are logged, but they do not stop the session.
+fn run_display_setup(config: &Config, display_value: &str, server_xauth_path: &str) {
+    let Some((program, args)) = config.x11.display_setup_command.split_first() else {
+        return;
+    };
 
+    info!("Running X display setup command");
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("DISPLAY", display_value)
+        .env("XAUTHORITY", server_xauth_path);
+
+    // The output is appended to the log of the X server
     let log_path = config
         .do_log
         .then_some(Path::new(&config.x11.xserver_log_path));
 
-    child.arg("-c").arg(format!(
-        "{} {display_value} vt{doubledigit_vtnr}",
-        &config.x11.xserver_path
-    ));
+    match LemursChild::spawn_appending(command, log_path).and_then(|mut child| child.wait()) {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("X display setup command failed. Status code: {status}"),
+        Err(err) => error!("Failed to run X display setup command. Reason: {err}"),
+    }
+}
 
-    let mut child = LemursChild::spawn(child, log_path).map_err(|err| {
-        error!("Failed to start X server. Reason: {}", err);
+/// Start the X server with the privileges of the user
+fn start_rootless_server(
+    user_info: &AuthUserInfo,
+    config: &Config,
+    args: Vec<String>,
+    display_value: &str,
+    vtnr_value: &str,
+    server_xauth_path: &str,
+) -> Result<XServer, XSetupError> {
+    info!("Starting rootless X server");
+
+    // The X server needs to open the virtual terminal itself
+    let vt_ownership = VtOwnership::give(vtnr_value, user_info.uid).map_err(|err| {
+        error!("Failed to give the user ownership of the virtual terminal. Reason: {err}");
         XSetupError::XServerStart
     })?;
 
-    // See note above
-    unsafe {
-        libc::signal(SIGUSR1, SIG_DFL);
-        signal(SIGUSR1, handle_sigusr1 as usize);
+    let process = start_server(
+        Some(user_info),
+        config,
+        &config.x11.xserver_path,
+        args,
+        display_value,
+        server_xauth_path,
+    )?;
+
+    Ok(XServer {
+        process,
+        vt_ownership: Some(vt_ownership),
+    })
+}
+
+/// Start the X server and wait until it accepts connections. The X server runs as root, unless
+/// `user_info` is given.
+///
+/// If the X server exits without writing its display number to the `-displayfd` pipe, it may not
+/// support `-displayfd`. Then, it is started once more and lemurs waits for its `SIGUSR1` signal
+/// instead.
+fn start_server(
+    user_info: Option<&AuthUserInfo>,
+    config: &Config,
+    server_path: &str,
+    args: Vec<String>,
+    display_value: &str,
+    server_xauth_path: &str,
+) -> Result<LemursChild, XSetupError> {
+    if config.x11.xserver_displayfd {
+        match spawn_server(
+            user_info,
+            config,
+            server_path,
+            &args,
+            display_value,
+            server_xauth_path,
+            true,
+        ) {
+            Err(XSetupError::XServerPrematureExit) => warn!(
+                "X server exited without reporting its display through `-displayfd`. Retrying without `-displayfd`"
+            ),
+            result => return result,
+        }
     }
 
-    // Wait for XServer to boot-up
-    let start_time = time::SystemTime::now();
-    loop {
-        if config.x11.xserver_timeout_secs == 0
-            || start_time
-                .elapsed()
-                .is_ok_and(|t| t.as_secs() > config.x11.xserver_timeout_secs.into())
-        {
-            break;
+    spawn_server(
+        user_info,
+        config,
+        server_path,
+        &args,
+        display_value,
+        server_xauth_path,
+        false,
+    )
+}
+
+/// Start the X server once and wait until it accepts connections. With `use_displayfd`, the X
+/// server reports that it is ready through a `-displayfd` pipe. Otherwise, it is detected with
+/// `SIGUSR1` or its socket.
+fn spawn_server(
+    user_info: Option<&AuthUserInfo>,
+    config: &Config,
+    server_path: &str,
+    args: &[String],
+    display_value: &str,
+    server_xauth_path: &str,
+    use_displayfd: bool,
+) -> Result<LemursChild, XSetupError> {
+    let display_number = display_number(display_value)
+        .ok_or_else(|| XSetupError::InvalidDisplay(display_value.to_string()))?;
+
+    let mut child = Command::new(server_path);
+    if let Some(user_info) = user_info {
+        child = lower_command_permissions_to_user(child, user_info, None);
+    }
+
+    let log_path = config
+        .do_log
+        .then_some(Path::new(&config.x11.xserver_log_path));
+
+    child.args(args).arg("-auth").arg(server_xauth_path);
+
+    // A rootless X server cannot detach from the terminal
+    if user_info.is_some() {
+        child.arg("-keeptty");
+    }
+
+    let displayfd = if use_displayfd {
+        Some(add_displayfd(&mut child).map_err(|err| {
+            error!("Failed to create the `-displayfd` pipe. Reason: {err}");
+            XSetupError::XServerStart
+        })?)
+    } else {
+        // Here we explicitely ignore the first USR defined signal. Xorg looks at whether this
+        // signal is ignored or not. If it is ignored, it will send that signal to the parent when
+        // it ready to receive connections. This is also how xinit does it.
+        //
+        // After we spawn the Xorg process, we need to make sure to quickly re-enable this signal
+        // as we need to listen to the signal by Xorg.
+        unsafe {
+            libc::signal(SIGUSR1, SIG_IGN);
         }
 
-        // This will be set by the `handle_sigusr1` signal handler.
-        if X_HAS_STARTED.load(std::sync::atomic::Ordering::SeqCst) {
-            break;
+        None
+    };
+
+    let start_time = Instant::now();
+
+    // When retrying without `-displayfd`, the log of the first attempt is kept
+    let is_retry = config.x11.xserver_displayfd && !use_displayfd;
+    let child = if is_retry {
+        LemursChild::spawn_appending(child, log_path)
+    } else {
+        LemursChild::spawn(child, log_path)
+    };
+
+    // Only the X server should hold the writing end of the pipe. Otherwise, lemurs does not see
+    // the pipe closing when the X server exits.
+    let displayfd = displayfd.map(|(sender, receiver)| {
+        drop(sender);
+        receiver
+    });
+
+    if displayfd.is_none() {
+        // See note above
+        unsafe {
+            libc::signal(SIGUSR1, SIG_DFL);
+            signal(
+                SIGUSR1,
+                handle_sigusr1 as extern "C" fn(i32) as libc::sighandler_t,
+            );
         }
+    }
 
+    let mut child = child.map_err(|err| {
+        error!("Failed to start X server. Reason: {}", err);
+        XSetupError::XServerStart
+    })?;
+
+    let timeout = (config.x11.xserver_timeout_secs != 0)
+        .then(|| Duration::from_secs(config.x11.xserver_timeout_secs.into()));
+
+    // Wait for XServer to boot-up
+    let result = match displayfd {
+        Some(receiver) => {
+            wait_for_displayfd(receiver, start_time, timeout).and_then(|started_display| {
+                match started_display {
+                    Some(started_display) if started_display != display_number => {
+                        Err(XSetupError::DisplayMismatch(started_display))
+                    }
+                    Some(_) => Ok(()),
+                    None => Err(XSetupError::XServerPrematureExit),
+                }
+            })
+        }
+        None => wait_for_signal(&mut child, display_number, start_time, timeout),
+    };
+
+    if let Err(err) = result {
         if let Some(status) = child.try_wait().unwrap_or(None) {
             error!(
                 "X server died before signaling it was ready to received connections. Status code: {status}."
             );
-
             return Err(XSetupError::XServerPrematureExit);
         }
 
-        thread::sleep(time::Duration::from_millis(XSTART_CHECK_INTERVAL_MILLIS));
+        child.kill().unwrap_or_else(|err| {
+            error!("Failed to kill Xorg after it failed to start. Reason: {err}");
+        });
+        let _ = child.wait();
+        return Err(err);
     }
 
-    // If the value is still `false`, this means we have time-ed out and Xorg is not running.
-    if !X_HAS_STARTED.load(std::sync::atomic::Ordering::SeqCst) {
-        child.kill().unwrap_or_else(|err| {
-            error!("Failed to kill Xorg after it timed out. Reason: {err}");
+    info!(
+        "It took X server {start_ms}ms to start",
+        start_ms = start_time.elapsed().as_millis()
+    );
+
+    info!("X server is running");
+
+    Ok(child)
+}
+
+/// Create the pipe that Xorg writes its display number to once it is ready to accept connections,
+/// and pass it to the X server with `-displayfd`.
+fn add_displayfd(command: &mut Command) -> io::Result<(Sender, Receiver)> {
+    let (sender, receiver) = pipe::new()?;
+    sender.set_nonblocking(false)?;
+
+    let fd = sender.as_raw_fd();
+    command.arg("-displayfd").arg(fd.to_string());
+
+    // The pipe is closed on exec by default. The X server needs to inherit the writing end.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
         });
-        return Err(XSetupError::XServerTimeout);
     }
 
-    if let Ok(x_server_start_time) = start_time.elapsed() {
-        info!(
-            "It took X server {start_ms}ms to start",
-            start_ms = x_server_start_time.as_millis()
-        );
+    Ok((sender, receiver))
+}
+
+/// Parse the line that Xorg writes to the `-displayfd` pipe
+fn parse_displayfd(output: &[u8]) -> Option<u32> {
+    std::str::from_utf8(output).ok()?.trim().parse().ok()
+}
+
+/// Wait until the X server writes its display number to the `-displayfd` pipe. This returns
+/// `None` if the pipe was closed before that, which means that the X server exited.
+///
+/// This uses its own poll instead of the one of the output logging in `wait_with_log`. That poll
+/// only exists when logging is enabled, and it is owned by the logging thread, which waits without
+/// a timeout.
+fn wait_for_displayfd(
+    mut receiver: Receiver,
+    start_time: Instant,
+    timeout: Option<Duration>,
+) -> Result<Option<u32>, XSetupError> {
+    const DISPLAYFD_RECV: Token = Token(0);
+
+    let poll_error = |err: io::Error| {
+        error!("Failed to wait for the `-displayfd` pipe. Reason: {err}");
+        XSetupError::XServerStart
+    };
+
+    let mut poll = Poll::new().map_err(poll_error)?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&mut receiver, DISPLAYFD_RECV, Interest::READABLE)
+        .map_err(poll_error)?;
+
+    let mut output = Vec::new();
+    loop {
+        let remaining = match timeout {
+            Some(timeout) => Some(
+                timeout
+                    .checked_sub(start_time.elapsed())
+                    .ok_or(XSetupError::XServerTimeout)?,
+            ),
+            None => None,
+        };
+
+        match poll.poll(&mut events, remaining) {
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(poll_error(err)),
+            Ok(()) => {}
+        }
+
+        let mut buf = [0u8; 32];
+        loop {
+            match receiver.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(n) => output.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(poll_error(err)),
+            }
+
+            if let Some(line_end) = output.iter().position(|b| *b == b'\n') {
+                return parse_displayfd(&output[..line_end])
+                    .map(Some)
+                    .ok_or(XSetupError::XServerStart);
+            }
+        }
     }
+}
+
+/// Wait until the X server sends `SIGUSR1` or accepts connections on its socket. This is used for
+/// X servers that do not support `-displayfd`.
+fn wait_for_signal(
+    child: &mut LemursChild,
+    display_number: u32,
+    start_time: Instant,
+    timeout: Option<Duration>,
+) -> Result<(), XSetupError> {
+    let result = loop {
+        if timeout.is_some_and(|timeout| start_time.elapsed() > timeout) {
+            break Err(XSetupError::XServerTimeout);
+        }
+
+        // This will be set by the `handle_sigusr1` signal handler.
+        if X_HAS_STARTED.load(std::sync::atomic::Ordering::SeqCst) {
+            break Ok(());
+        }
+
+        // A rootless X server is not allowed to signal lemurs, so it is also checked whether the
+        // X server accepts connections on its socket.
+        if UnixStream::connect(socket_path(display_number)).is_ok() {
+            break Ok(());
+        }
+
+        if child.try_wait().unwrap_or(None).is_some() {
+            break Err(XSetupError::XServerPrematureExit);
+        }
+
+        thread::sleep(Duration::from_millis(XSTART_CHECK_INTERVAL_MILLIS));
+    };
 
     X_HAS_STARTED.store(false, std::sync::atomic::Ordering::SeqCst);
 
-    info!("X server is running");
+    result
+}
 
-    Ok(child)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displayfd_output() {
+        assert_eq!(parse_displayfd(b"0"), Some(0));
+        assert_eq!(parse_displayfd(b"12 "), Some(12));
+        assert_eq!(parse_displayfd(b""), None);
+        assert_eq!(parse_displayfd(b":1"), None);
+    }
+
+    #[test]
+    fn xserver_placeholders() {
+        let args = [
+            "%display%".to_string(),
+            "vt%vt%".to_string(),
+            "-nolisten".to_string(),
+            "tcp".to_string(),
+        ];
+
+        assert_eq!(
+            fill_placeholders(&args, &[("%display%", ":1"), ("%vt%", "02")]),
+            vec![":1", "vt02", "-nolisten", "tcp"]
+        );
+        assert_eq!(
+            fill_placeholders(&args, &[("%display%", ":1")]),
+            vec![":1", "vt%vt%", "-nolisten", "tcp"]
+        );
+    }
 }
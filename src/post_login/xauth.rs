@@ -0,0 +1,136 @@
+//! Writing Xauthority files, as read by the X server (`-auth`) and by X clients (`XAUTHORITY`).
+//!
+//! An Xauthority file is a sequence of entries. Every entry consists of a 16-bit address family,
+//! followed by the address, the display number, the authorization name and the authorization
+//! data. These fields are all prefixed with their 16-bit length. All integers are big-endian.
+
+use std::fs::{remove_file, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{fchown, OpenOptionsExt};
+use std::path::Path;
+
+use rand::Rng;
+
+/// The address family of local connections, where the address is the hostname
+const FAMILY_LOCAL: u16 = 256;
+/// The address family that matches any address
+const FAMILY_WILD: u16 = 65535;
+
+const MIT_MAGIC_COOKIE: &[u8] = b"MIT-MAGIC-COOKIE-1";
+
+pub type Cookie = [u8; 16];
+
+pub fn new_cookie() -> Cookie {
+    rand::rng().random()
+}
+
+fn hostname() -> Option<Vec<u8>> {
+    let mut buffer = [0u8; 256];
+
+    // SAFETY: The buffer is valid for its length. The hostname is truncated if it is too long.
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return None;
+    }
+
+    let length = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    Some(buffer[..length].to_vec())
+}
+
+fn push_field(buffer: &mut Vec<u8>, field: &[u8]) {
+    // All fields are far shorter than `u16::MAX`
+    buffer.extend_from_slice(&(field.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(field);
+}
+
+fn push_entry(buffer: &mut Vec<u8>, family: u16, address: &[u8], display: &[u8], cookie: &Cookie) {
+    buffer.extend_from_slice(&family.to_be_bytes());
+    push_field(buffer, address);
+    push_field(buffer, display);
+    push_field(buffer, MIT_MAGIC_COOKIE);
+    push_field(buffer, cookie);
+}
+
+/// Encode the entries that authorize local connections to a display with a cookie. The entry
+/// for the hostname is what `xauth add` writes. The wildcard entry keeps working when the
+/// hostname changes during the session.
+pub fn encode(display_number: u32, cookie: &Cookie, hostname: Option<&[u8]>) -> Vec<u8> {
+    let display = display_number.to_string();
+    let mut buffer = Vec::new();
+
+    if let Some(hostname) = hostname {
+        push_entry(
+            &mut buffer,
+            FAMILY_LOCAL,
+            hostname,
+            display.as_bytes(),
+            cookie,
+        );
+    }
+    push_entry(&mut buffer, FAMILY_WILD, &[], display.as_bytes(), cookie);
+
+    buffer
+}
+
+/// Write an Xauthority file for a display, replacing any existing file. The file is only
+/// accessible to its owner, which is changed to `owner` if it is given.
+pub fn write_file(
+    path: &Path,
+    display_number: u32,
+    cookie: &Cookie,
+    owner: Option<(libc::uid_t, libc::gid_t)>,
+) -> io::Result<()> {
+    match remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
+    // The file is created as root, possibly in a directory that is owned by the user. Therefore,
+    // never follow symbolic links and never reuse an existing file.
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+
+    if let Some((uid, gid)) = owner {
+        fchown(&file, Some(uid), Some(gid))?;
+    }
+
+    file.write_all(&encode(display_number, cookie, hostname().as_deref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIE: Cookie = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+
+    #[test]
+    fn wildcard_entry() {
+        let mut expected = vec![0xff, 0xff, 0, 0, 0, 2, b'1', b'2', 0, 18];
+        expected.extend_from_slice(b"MIT-MAGIC-COOKIE-1");
+        expected.extend_from_slice(&[0, 16]);
+        expected.extend_from_slice(&COOKIE);
+
+        assert_eq!(encode(12, &COOKIE, None), expected);
+    }
+
+    #[test]
+    fn hostname_entry() {
+        let encoded = encode(0, &COOKIE, Some(b"lemurs"));
+
+        let mut expected = vec![0x01, 0x00, 0, 6];
+        expected.extend_from_slice(b"lemurs");
+        expected.extend_from_slice(&[0, 1, b'0', 0, 18]);
+        expected.extend_from_slice(b"MIT-MAGIC-COOKIE-1");
+        expected.extend_from_slice(&[0, 16]);
+        expected.extend_from_slice(&COOKIE);
+
+        assert!(encoded.starts_with(&expected));
+        assert_eq!(encoded[expected.len()..], encode(0, &COOKIE, None));
+    }
+}
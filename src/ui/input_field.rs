@@ -316,6 +316,23 @@ impl InputFieldWidget {
         frame.render_widget(widget, area);
     }
 
+    /// Render only the borders and title of the input field. This returns the area within the
+    /// borders, so that another widget can be rendered inside.
+    pub fn render_block(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        is_focused: bool,
+    ) -> Rect {
+        let area = self.constraint_area(area);
+        let block = self.get_block(is_focused);
+        let inner = block.inner(area);
+
+        frame.render_widget(block, area);
+
+        inner
+    }
+
     pub(crate) fn key_press(
         &mut self,
         key_code: KeyCode,
@@ -352,6 +369,15 @@ impl InputFieldWidget {
         self.cursor = content.len() as u16;
         self.content = content.to_string();
     }
+
+    /// Change the title shown above the input field
+    pub fn set_title(&mut self, title: &str) {
+        self.style.title = title.to_string();
+    }
+
+    pub fn set_display_type(&mut self, display_type: InputFieldDisplayType) {
+        self.display_type = display_type;
+    }
 }
 
 #[cfg(test)]
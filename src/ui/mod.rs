@@ -1,13 +1,15 @@
 use log::{error, info, warn};
 
 use std::io;
-use std::sync::mpsc::channel;
+use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
+use crate::auth::{try_auth, AuthConversation, AuthUserInfo, PasswordChange, SessionHints};
 use crate::config::{Config, FocusBehaviour, SwitcherVisibility};
 use crate::info_caching::{get_cached_information, set_cache};
-use crate::post_login::PostLoginEnvironment;
+use crate::post_login::{get_envs, PostLoginEnvironment, SessionUser};
+use crate::user_list::get_users;
 use crate::{start_session, Hooks, StartSessionError};
 use status_message::StatusMessage;
 
@@ -105,7 +107,7 @@ impl LoginFormStatusMessage {
 }
 
 /// All the different modes for input
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 enum InputMode {
     /// Using the env switcher widget
     Switcher,
@@ -116,6 +118,18 @@ enum InputMode {
     /// Typing within the Password input field
     Password,
 
+    /// Answering a prompt of the authentication modules
+    Prompt,
+
+    /// Typing the current password while changing an expired password
+    CurrentPassword,
+
+    /// Typing the new password while changing an expired password
+    NewPassword,
+
+    /// Typing the confirmation of the new password while changing an expired password
+    ConfirmPassword,
+
     /// Nothing selected
     Normal,
 }
@@ -136,6 +150,10 @@ impl InputMode {
             Switcher => Username,
             Username => Password,
             Password => Password,
+            Prompt => Prompt,
+            CurrentPassword => NewPassword,
+            NewPassword => ConfirmPassword,
+            ConfirmPassword => ConfirmPassword,
         }
     }
 
@@ -154,6 +172,10 @@ impl InputMode {
                 }
             }
             Password => Username,
+            Prompt => Prompt,
+            CurrentPassword => CurrentPassword,
+            NewPassword => CurrentPassword,
+            ConfirmPassword => NewPassword,
         }
     }
 }
@@ -171,8 +193,16 @@ struct Widgets {
     panel: PanelWidget,
     key_menu: KeyMenuWidget,
     environment: Arc<Mutex<SwitcherWidget<PostLoginEnvironment>>>,
+    /// The username for which the user sessions in the environment switcher were loaded
+    environment_user: Arc<Mutex<String>>,
     username: Arc<Mutex<InputFieldWidget>>,
+    /// The user picker that replaces typing the username, if it is enabled
+    users: Option<Arc<Mutex<SwitcherWidget<String>>>>,
     password: Arc<Mutex<InputFieldWidget>>,
+    prompt: Arc<Mutex<InputFieldWidget>>,
+    current_password: Arc<Mutex<InputFieldWidget>>,
+    new_password: Arc<Mutex<InputFieldWidget>>,
+    confirm_password: Arc<Mutex<InputFieldWidget>>,
 }
 
 impl Widgets {
@@ -194,6 +224,15 @@ impl Widgets {
             }
         }
     }
+    fn users_guard(&self) -> Option<MutexGuard<'_, SwitcherWidget<String>>> {
+        self.users.as_ref().map(|users| match users.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        })
+    }
     fn password_guard(&self) -> MutexGuard<'_, InputFieldWidget> {
         match self.password.lock() {
             Ok(guard) => guard,
@@ -203,6 +242,42 @@ impl Widgets {
             }
         }
     }
+    fn prompt_guard(&self) -> MutexGuard<'_, InputFieldWidget> {
+        match self.prompt.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    fn current_password_guard(&self) -> MutexGuard<'_, InputFieldWidget> {
+        match self.current_password.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    fn new_password_guard(&self) -> MutexGuard<'_, InputFieldWidget> {
+        match self.new_password.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    fn confirm_password_guard(&self) -> MutexGuard<'_, InputFieldWidget> {
+        match self.confirm_password.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
 
     fn get_environment(&self) -> Option<(String, PostLoginEnvironment)> {
         self.environment_guard()
@@ -213,10 +288,50 @@ impl Widgets {
         self.environment_guard().try_select(title);
     }
     fn get_username(&self) -> String {
-        self.username_guard().get_content()
+        match self.users_guard() {
+            Some(users) => users
+                .selected()
+                .map(|item| item.content.clone())
+                .unwrap_or_default(),
+            None => self.username_guard().get_content(),
+        }
     }
     fn set_username(&self, content: &str) {
-        self.username_guard().set_content(content)
+        match self.users_guard() {
+            Some(mut users) => users.try_select_content(&content.to_string()),
+            None => self.username_guard().set_content(content),
+        }
+    }
+    /// Reload the environments when the username changed, so that the switcher shows the
+    /// sessions of that user
+    fn refresh_environments(&self, config: &Config) {
+        if !config.user_sessions.enabled {
+            return;
+        }
+
+        let username = self.get_username();
+        let mut environment_user = match self.environment_user.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        if *environment_user == username {
+            return;
+        }
+
+        info!("Reloading the environments for user '{username}'");
+
+        let user = SessionUser::from_username(&username);
+        let items = get_envs(config, user.as_ref())
+            .into_iter()
+            .map(|(title, content)| SwitcherItem::new(title, content))
+            .collect();
+        self.environment_guard().set_items(items);
+
+        *environment_user = username;
     }
     fn get_password(&self) -> String {
         self.password_guard().get_content()
@@ -224,6 +339,181 @@ impl Widgets {
     fn clear_password(&self) {
         self.password_guard().clear()
     }
+    fn clear_password_change(&self) {
+        self.current_password_guard().clear();
+        self.new_password_guard().clear();
+        self.confirm_password_guard().clear();
+    }
+}
+
+/// The conversation of the authentication modules with the user through the login form.
+///
+/// Prompts are shown as an additional input field in the place of the password field. Messages
+/// are shown as status messages. Changing an expired password replaces the whole form with the
+/// current, new and confirmation password fields.
+struct FormConversation {
+    widgets: Widgets,
+    input_mode: LoginFormInputMode,
+    status_message: LoginFormStatusMessage,
+    ui_requests: Sender<UIThreadRequest>,
+    replacement_character: String,
+}
+
+impl FormConversation {
+    fn redraw(&self) {
+        if let Err(err) = self.ui_requests.send(UIThreadRequest::Redraw) {
+            warn!("Failed to send UI request. Reason: {}", err);
+        }
+    }
+}
+
+impl AuthConversation for FormConversation {
+    fn prompt(&mut self, message: &str, echo: bool) -> Option<String> {
+        info!("Prompting user for '{message}'");
+
+        let previous_mode = self.input_mode.get();
+
+        {
+            let mut prompt = self.widgets.prompt_guard();
+            prompt.clear();
+            prompt.set_title(message.trim());
+            prompt.set_display_type(if echo {
+                InputFieldDisplayType::Echo
+            } else {
+                InputFieldDisplayType::Replace(self.replacement_character.clone())
+            });
+        }
+        self.input_mode.set(InputMode::Prompt);
+        self.redraw();
+
+        let answer = loop {
+            match event::read() {
+                Ok(Event::Key(key)) => match key.code {
+                    KeyCode::Enter => break Some(self.widgets.prompt_guard().get_content()),
+                    KeyCode::Esc => break None,
+                    k => {
+                        self.widgets.prompt_guard().key_press(k, key.modifiers);
+                    }
+                },
+                Ok(_) => {}
+                Err(err) => {
+                    error!("Failed to read input for prompt. Reason: {err}");
+                    break None;
+                }
+            }
+
+            self.redraw();
+        };
+
+        self.widgets.prompt_guard().clear();
+        self.input_mode.set(previous_mode);
+        self.redraw();
+
+        answer
+    }
+
+    fn info(&mut self, message: &str) {
+        self.status_message
+            .set(InfoStatusMessage::AuthenticationMessage(
+                message.to_string(),
+            ));
+        self.redraw();
+    }
+
+    fn error(&mut self, message: &str) {
+        self.status_message
+            .set(ErrorStatusMessage::AuthenticationMessage(
+                message.to_string(),
+            ));
+        self.redraw();
+    }
+
+    fn change_password(&mut self) -> Option<PasswordChange> {
+        info!("Asking user for a new password");
+
+        let previous_mode = self.input_mode.get();
+
+        self.widgets.clear_password_change();
+        self.input_mode.set(InputMode::CurrentPassword);
+        self.status_message.set(InfoStatusMessage::PasswordExpired);
+        self.redraw();
+
+        let change = loop {
+            let key = match event::read() {
+                Ok(Event::Key(key)) => key,
+                Ok(_) => continue,
+                Err(err) => {
+                    error!("Failed to read input for password change. Reason: {err}");
+                    break None;
+                }
+            };
+
+            match (key.code, self.input_mode.get(), key.modifiers) {
+                (KeyCode::Esc, _, _) => break None,
+
+                (KeyCode::Enter, InputMode::ConfirmPassword, _) => {
+                    let current = self.widgets.current_password_guard().get_content();
+                    let new = self.widgets.new_password_guard().get_content();
+                    let confirm = self.widgets.confirm_password_guard().get_content();
+
+                    if new.is_empty() {
+                        self.status_message
+                            .set(ErrorStatusMessage::EmptyNewPassword);
+                        self.input_mode.set(InputMode::NewPassword);
+                    } else if new != confirm {
+                        self.widgets.new_password_guard().clear();
+                        self.widgets.confirm_password_guard().clear();
+                        self.status_message
+                            .set(ErrorStatusMessage::PasswordMismatch);
+                        self.input_mode.set(InputMode::NewPassword);
+                    } else {
+                        break Some(PasswordChange { current, new });
+                    }
+                }
+
+                (KeyCode::Up | KeyCode::BackTab, _, _)
+                | (KeyCode::Tab, _, KeyModifiers::ALT | KeyModifiers::SHIFT)
+                | (KeyCode::Char('p'), _, KeyModifiers::CONTROL) => {
+                    self.input_mode.prev(true);
+                }
+
+                (KeyCode::Enter | KeyCode::Down | KeyCode::Tab, _, _)
+                | (KeyCode::Char('n'), _, KeyModifiers::CONTROL) => {
+                    self.input_mode.next(true);
+                }
+
+                (k, mode, modifiers) => {
+                    let status_message_opt = match mode {
+                        InputMode::CurrentPassword => self
+                            .widgets
+                            .current_password_guard()
+                            .key_press(k, modifiers),
+                        InputMode::NewPassword => {
+                            self.widgets.new_password_guard().key_press(k, modifiers)
+                        }
+                        InputMode::ConfirmPassword => self
+                            .widgets
+                            .confirm_password_guard()
+                            .key_press(k, modifiers),
+                        _ => None,
+                    };
+
+                    if let Some(status_msg) = status_message_opt {
+                        self.status_message.set(status_msg);
+                    }
+                }
+            }
+
+            self.redraw();
+        };
+
+        self.widgets.clear_password_change();
+        self.input_mode.set(previous_mode);
+        self.status_message.set(InfoStatusMessage::Authenticating);
+        self.redraw();
+
+        change
+    }
 }
 
 /// App holds the state of the application
@@ -275,6 +565,10 @@ impl LoginForm {
                 self.widgets.set_username(username);
             }
         }
+
+        // The cached environment can be one of the sessions of the user
+        self.widgets.refresh_environments(&self.config);
+
         if env_remember {
             if let Some(env) = cached.environment() {
                 info!("Loading environment '{}' from cache", env);
@@ -295,17 +589,19 @@ impl LoginForm {
                     config.system_shell.clone(),
                 ),
                 environment: Arc::new(Mutex::new(SwitcherWidget::new(
-                    crate::post_login::get_envs(&config)
+                    get_envs(&config, None)
                         .into_iter()
                         .map(|(title, content)| SwitcherItem::new(title, content))
                         .collect(),
                     config.environment_switcher.clone(),
                 ))),
+                environment_user: Arc::new(Mutex::new(String::new())),
                 username: Arc::new(Mutex::new(InputFieldWidget::new(
                     InputFieldDisplayType::Echo,
                     config.username_field.style.clone(),
                     String::default(),
                 ))),
+                users: get_user_picker(&config).map(|users| Arc::new(Mutex::new(users))),
                 password: Arc::new(Mutex::new(InputFieldWidget::new(
                     InputFieldDisplayType::Replace(
                         config
@@ -316,6 +612,20 @@ impl LoginForm {
                     config.password_field.style.clone(),
                     String::default(),
                 ))),
+                prompt: Arc::new(Mutex::new(InputFieldWidget::new(
+                    InputFieldDisplayType::Echo,
+                    config.password_field.style.clone(),
+                    String::default(),
+                ))),
+                current_password: Arc::new(Mutex::new(password_change_field(
+                    &config,
+                    "Current password",
+                ))),
+                new_password: Arc::new(Mutex::new(password_change_field(&config, "New password"))),
+                confirm_password: Arc::new(Mutex::new(password_change_field(
+                    &config,
+                    "Confirm new password",
+                ))),
             },
             config,
         }
@@ -356,7 +666,12 @@ impl LoginForm {
         let key_menu = self.widgets.key_menu.clone();
         let environment = self.widgets.environment.clone();
         let username = self.widgets.username.clone();
+        let users = self.widgets.users.clone();
         let password = self.widgets.password.clone();
+        let prompt = self.widgets.prompt.clone();
+        let current_password = self.widgets.current_password.clone();
+        let new_password = self.widgets.new_password.clone();
+        let confirm_password = self.widgets.confirm_password.clone();
         let panel_position = self.config.panel.position.clone();
 
         let draw_action = terminal.draw(|f| {
@@ -369,7 +684,14 @@ impl LoginForm {
                 key_menu.clone(),
                 environment.clone(),
                 username.clone(),
+                users.clone(),
                 password.clone(),
+                prompt.clone(),
+                [
+                    current_password.clone(),
+                    new_password.clone(),
+                    confirm_password.clone(),
+                ],
                 input_mode.get(),
                 status_message.get(),
             );
@@ -435,25 +757,50 @@ impl LoginForm {
                 if let Ok(Event::Key(key)) = event::read() {
                     match (key.code, input_mode.get(), key.modifiers) {
                         (KeyCode::Enter, InputMode::Password, _) => {
-                            if self.preview {
-                                // This is only for demonstration purposes
-                                status_message.set(InfoStatusMessage::Authenticating);
-                                send_ui_request(UIThreadRequest::Redraw);
-                                std::thread::sleep(Duration::from_secs(2));
+                            let environment =
+                                self.widgets.get_environment().map(|(_, content)| content);
+                            let username = self.widgets.get_username();
+                            let password = self.widgets.get_password();
+                            let config = self.config.clone();
+                            let conversation = FormConversation {
+                                widgets: self.widgets.clone(),
+                                input_mode: input_mode.clone(),
+                                status_message: status_message.clone(),
+                                ui_requests: req_send_channel.clone(),
+                                replacement_character: config
+                                    .password_field
+                                    .content_replacement_character
+                                    .to_string(),
+                            };
 
-                                status_message.set(InfoStatusMessage::LoggingIn);
-                                send_ui_request(UIThreadRequest::Redraw);
-                                std::thread::sleep(Duration::from_secs(2));
+                            // X sessions on a virtual X server can also be started in preview mode
+                            let is_virtual_x = config.x11.virtual_server
+                                && matches!(environment, Some(PostLoginEnvironment::X { .. }));
+
+                            if self.preview && !is_virtual_x {
+                                // Only authenticate, since starting the environment is not
+                                // possible from within an existing session.
+                                pre_auth();
+
+                                match preview_login(
+                                    &config,
+                                    environment.as_ref(),
+                                    &username,
+                                    &password,
+                                    Box::new(conversation),
+                                ) {
+                                    Ok(auth_session) => {
+                                        status_message.set(InfoStatusMessage::LoggingIn);
+                                        send_ui_request(UIThreadRequest::Redraw);
+                                        std::thread::sleep(Duration::from_secs(2));
+                                        drop(auth_session);
 
-                                status_message.clear();
+                                        status_message.clear();
+                                    }
+                                    Err(err) => status_message.set(err),
+                                }
                                 send_ui_request(UIThreadRequest::Redraw);
                             } else {
-                                let environment =
-                                    self.widgets.get_environment().map(|(_, content)| content);
-                                let username = self.widgets.get_username();
-                                let password = self.widgets.get_password();
-                                let config = self.config.clone();
-
                                 let Some(post_login_env) = environment else {
                                     status_message.set(ErrorStatusMessage::NoGraphicalEnvironment);
                                     send_ui_request(UIThreadRequest::Redraw);
@@ -466,11 +813,17 @@ impl LoginForm {
                                     &post_login_env,
                                     &hooks,
                                     &config,
+                                    self.preview,
+                                    Box::new(conversation),
                                 ) {
                                     Ok(()) => {}
                                     Err(StartSessionError::AuthenticationError(err)) => {
-                                        status_message
-                                            .set(ErrorStatusMessage::AuthenticationError(err));
+                                        status_message.set(
+                                            ErrorStatusMessage::AuthenticationError(
+                                                err.user_message(&config.authentication_messages)
+                                                    .to_string(),
+                                            ),
+                                        );
                                         send_ui_request(UIThreadRequest::Redraw);
                                     }
                                     Err(StartSessionError::EnvironmentStartError(err)) => {
@@ -536,9 +889,10 @@ impl LoginForm {
                                 InputMode::Switcher => {
                                     self.widgets.environment_guard().key_press(k)
                                 }
-                                InputMode::Username => {
-                                    self.widgets.username_guard().key_press(k, modifiers)
-                                }
+                                InputMode::Username => match self.widgets.users_guard() {
+                                    Some(mut users) => users.key_press(k),
+                                    None => self.widgets.username_guard().key_press(k, modifiers),
+                                },
                                 InputMode::Password => {
                                     self.widgets.password_guard().key_press(k, modifiers)
                                 }
@@ -553,6 +907,12 @@ impl LoginForm {
                     };
                 }
 
+                // Only reload the environments once the username is entered, instead of on every
+                // typed character
+                if input_mode.get() != InputMode::Username || self.widgets.users.is_some() {
+                    self.widgets.refresh_environments(&self.config);
+                }
+
                 send_ui_request(UIThreadRequest::Redraw);
             }
         });
@@ -573,7 +933,14 @@ impl LoginForm {
                             key_menu.clone(),
                             environment.clone(),
                             username.clone(),
+                            users.clone(),
                             password.clone(),
+                            prompt.clone(),
+                            [
+                                current_password.clone(),
+                                new_password.clone(),
+                                confirm_password.clone(),
+                            ],
                             input_mode.get(),
                             status_message.get(),
                         );
@@ -607,6 +974,71 @@ impl LoginForm {
     }
 }
 
+/// Authenticate the user of the login form in preview mode. No environment is started, since
+/// that is not possible from within an existing session.
+fn preview_login(
+    config: &Config,
+    environment: Option<&PostLoginEnvironment>,
+    username: &str,
+    password: &str,
+    conversation: Box<dyn AuthConversation>,
+) -> Result<AuthUserInfo, ErrorStatusMessage> {
+    // No X server is started, so there is no display to select
+    let hints = SessionHints::new(
+        config,
+        environment.unwrap_or(&PostLoginEnvironment::Shell),
+        None,
+    );
+
+    try_auth(username, password, config, &hints, conversation, true).map_err(|err| {
+        ErrorStatusMessage::AuthenticationError(
+            err.user_message(&config.authentication_messages)
+                .to_string(),
+        )
+    })
+}
+
+/// Create the user picker, if it is enabled and there are users to pick from
+fn get_user_picker(config: &Config) -> Option<SwitcherWidget<String>> {
+    if !config.username_field.user_picker.enabled {
+        return None;
+    }
+
+    let users = get_users(&config.username_field.user_picker);
+    if users.is_empty() {
+        warn!("No users found for the user picker. Falling back to typing the username");
+        return None;
+    }
+
+    // The user picker is styled like the environment switcher, but is always visible.
+    let mut switcher_config = config.environment_switcher.clone();
+    switcher_config.switcher_visibility = SwitcherVisibility::Visible;
+
+    Some(SwitcherWidget::new(
+        users
+            .into_iter()
+            .map(|(title, username)| SwitcherItem::new(title, username))
+            .collect(),
+        switcher_config,
+    ))
+}
+
+/// Create one of the input fields used to change an expired password
+fn password_change_field(config: &Config, title: &str) -> InputFieldWidget {
+    let mut field = InputFieldWidget::new(
+        InputFieldDisplayType::Replace(
+            config
+                .password_field
+                .content_replacement_character
+                .to_string(),
+        ),
+        config.password_field.style.clone(),
+        String::default(),
+    );
+    field.set_title(title);
+    field
+}
+
 #[allow(clippy::too_many_arguments)]
 fn login_form_render(
     frame: &mut Frame,
@@ -616,13 +1048,42 @@ fn login_form_render(
     key_menu: KeyMenuWidget,
     environment: Arc<Mutex<SwitcherWidget<PostLoginEnvironment>>>,
     username: Arc<Mutex<InputFieldWidget>>,
+    users: Option<Arc<Mutex<SwitcherWidget<String>>>>,
     password: Arc<Mutex<InputFieldWidget>>,
+    prompt: Arc<Mutex<InputFieldWidget>>,
+    password_change: [Arc<Mutex<InputFieldWidget>>; 3],
     input_mode: InputMode,
     status_message: Option<StatusMessage>,
 ) {
     background.render(frame);
     panel.render(frame, chunks.panel_root);
     key_menu.render(frame, chunks.key_menu);
+
+    if matches!(
+        input_mode,
+        InputMode::CurrentPassword | InputMode::NewPassword | InputMode::ConfirmPassword
+    ) {
+        let [current, new, confirm] = password_change;
+        let fields = [
+            (current, chunks.switcher, InputMode::CurrentPassword),
+            (new, chunks.username_field, InputMode::NewPassword),
+            (confirm, chunks.password_field, InputMode::ConfirmPassword),
+        ];
+
+        for (field, area, mode) in fields {
+            field
+                .lock()
+                .unwrap_or_else(|err| {
+                    error!("Failed to lock password change field. Reason: {}", err);
+                    std::process::exit(1);
+                })
+                .render(frame, area, input_mode == mode);
+        }
+
+        StatusMessage::render(status_message, frame, chunks.status_message);
+        return;
+    }
+
     environment
         .lock()
         .unwrap_or_else(|err| {
@@ -634,29 +1095,78 @@ fn login_form_render(
             chunks.switcher,
             matches!(input_mode, InputMode::Switcher),
         );
-    username
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock username. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.username_field,
-            matches!(input_mode, InputMode::Username),
-        );
-    password
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock password. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.password_field,
-            matches!(input_mode, InputMode::Password),
-        );
+    let username = username.lock().unwrap_or_else(|err| {
+        error!("Failed to lock username. Reason: {}", err);
+        std::process::exit(1);
+    });
+    match users {
+        Some(users) => {
+            let is_focused = matches!(input_mode, InputMode::Username);
+            let area = username.render_block(frame, chunks.username_field, is_focused);
+
+            users
+                .lock()
+                .unwrap_or_else(|err| {
+                    error!("Failed to lock user picker. Reason: {}", err);
+                    std::process::exit(1);
+                })
+                .render(frame, area, is_focused);
+        }
+        None => {
+            let mut username = username;
+            username.render(
+                frame,
+                chunks.username_field,
+                matches!(input_mode, InputMode::Username),
+            );
+        }
+    }
+    if matches!(input_mode, InputMode::Prompt) {
+        prompt
+            .lock()
+            .unwrap_or_else(|err| {
+                error!("Failed to lock prompt. Reason: {}", err);
+                std::process::exit(1);
+            })
+            .render(frame, chunks.password_field, true);
+    } else {
+        password
+            .lock()
+            .unwrap_or_else(|err| {
+                error!("Failed to lock password. Reason: {}", err);
+                std::process::exit(1);
+            })
+            .render(
+                frame,
+                chunks.password_field,
+                matches!(input_mode, InputMode::Password),
+            );
+    }
 
     // Display Status Message
     StatusMessage::render(status_message, frame, chunks.status_message);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::NoConversation;
+    use crate::config::AuthBackendKind;
+
+    #[test]
+    fn preview_submit() {
+        let mut config = Config {
+            auth_backend: AuthBackendKind::Mock,
+            ..Default::default()
+        };
+        config.authentication_messages.invalid_credentials = "Try again".to_string();
+
+        assert!(preview_login(&config, None, "demo", "demo", Box::new(NoConversation)).is_ok());
+
+        let Err(err) = preview_login(&config, None, "demo", "wrong", Box::new(NoConversation))
+        else {
+            panic!("Logged in with a wrong password");
+        };
+        assert_eq!(&*Box::<str>::from(err), "Try again");
+    }
+}
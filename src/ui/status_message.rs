@@ -3,15 +3,16 @@ use ratatui::style::{Color, Style};
 use ratatui::widgets::Paragraph;
 use ratatui::Frame;
 
-use crate::auth::AuthenticationError;
-
 #[derive(Clone)]
 pub enum ErrorStatusMessage {
-    AuthenticationError(AuthenticationError),
+    AuthenticationError(String),
+    AuthenticationMessage(String),
     NoGraphicalEnvironment,
     FailedGraphicalEnvironment,
     FailedDesktop,
     FailedPowerControl(String),
+    EmptyNewPassword,
+    PasswordMismatch,
 }
 
 impl From<ErrorStatusMessage> for Box<str> {
@@ -19,13 +20,16 @@ impl From<ErrorStatusMessage> for Box<str> {
         use ErrorStatusMessage::*;
 
         match err {
-            AuthenticationError(_) => "Authentication failed".into(),
+            AuthenticationError(msg) => msg.into(),
+            AuthenticationMessage(msg) => msg.into(),
             NoGraphicalEnvironment => "No graphical environment specified".into(),
             FailedGraphicalEnvironment => "Failed booting into the graphical environment".into(),
             FailedDesktop => "Failed booting into desktop environment".into(),
             FailedPowerControl(name) => {
                 format!("Failed to {name}... Check the logs for more information").into()
             }
+            EmptyNewPassword => "The new password cannot be empty".into(),
+            PasswordMismatch => "The new passwords do not match".into(),
         }
     }
 }
@@ -36,10 +40,12 @@ impl From<ErrorStatusMessage> for StatusMessage {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum InfoStatusMessage {
     LoggingIn,
     Authenticating,
+    AuthenticationMessage(String),
+    PasswordExpired,
 }
 
 impl From<InfoStatusMessage> for Box<str> {
@@ -49,6 +55,8 @@ impl From<InfoStatusMessage> for Box<str> {
         match info {
             LoggingIn => "Authentication successful. Logging in...".into(),
             Authenticating => "Verifying credentials".into(),
+            AuthenticationMessage(msg) => msg.into(),
+            PasswordExpired => "Password expired. Please choose a new password".into(),
         }
     }
 }
@@ -64,6 +64,28 @@ impl<T> Switcher<T> {
         }
     }
 
+    pub fn try_select_content(&mut self, content: &T)
+    where
+        T: PartialEq,
+    {
+        // Only set the selected if we find matching content
+        if let Some(selected) = self.items.iter().position(|item| &item.content == content) {
+            self.selected = Some(selected);
+        } else {
+            warn!("Failed to find selection with the given content");
+        }
+    }
+
+    /// Replace the items, keeping the selection if an item with the same title still exists
+    fn set_items(&mut self, items: Vec<SwitcherItem<T>>) {
+        let selected_title = self.current().map(|item| item.title.clone());
+
+        self.selected = selected_title
+            .and_then(|title| items.iter().position(|item| item.title == title))
+            .or(if items.is_empty() { None } else { Some(0) });
+        self.items = items;
+    }
+
     fn next_index(&self, index: usize) -> Option<usize> {
         let next_index = index + 1;
 
@@ -139,6 +161,17 @@ impl<T> SwitcherWidget<T> {
         self.selector.try_select(title)
     }
 
+    pub fn try_select_content(&mut self, content: &T)
+    where
+        T: PartialEq,
+    {
+        self.selector.try_select_content(content)
+    }
+
+    pub fn set_items(&mut self, items: Vec<SwitcherItem<T>>) {
+        self.selector.set_items(items)
+    }
+
     fn do_show_neighbours(&self, area_width: usize) -> bool {
         self.config.show_neighbours
             && usize::from(self.config.max_display_length) * 3
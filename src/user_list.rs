@@ -0,0 +1,119 @@
+use std::ffi::CStr;
+use std::path::Path;
+
+use log::{info, warn};
+use uzers::os::unix::UserExt;
+
+use crate::config::UserPickerConfig;
+
+/// The shells that indicate that a user is not allowed to log in
+const NON_LOGIN_SHELLS: [&str; 2] = ["nologin", "false"];
+
+/// The shell of users with an empty shell field in their passwd entry
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+/// Whether the shell of a passwd entry allows the user to log in
+fn is_login_shell(shell: &Path) -> bool {
+    let shell = if shell.as_os_str().is_empty() {
+        Path::new(DEFAULT_SHELL)
+    } else {
+        shell
+    };
+
+    shell
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| !NON_LOGIN_SHELLS.contains(&name))
+}
+
+/// Fetch the real name from the GECOS field of the passwd entry of a user
+fn get_real_name(uid: libc::uid_t) -> Option<String> {
+    let mut buffer = vec![0; 4096];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    let status = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+
+    if status != 0 || result.is_null() || passwd.pw_gecos.is_null() {
+        return None;
+    }
+
+    // SAFETY: The GECOS field is a NULL-terminated string that lives in `buffer`.
+    let gecos = unsafe { CStr::from_ptr(passwd.pw_gecos) }.to_string_lossy();
+
+    // The GECOS field is a comma separated list, starting with the full name.
+    let real_name = gecos.split(',').next().unwrap_or_default().trim();
+
+    if real_name.is_empty() {
+        None
+    } else {
+        Some(real_name.to_string())
+    }
+}
+
+/// Get the users that can be selected in the user picker. This returns the title shown for each
+/// user together with their username.
+pub fn get_users(config: &UserPickerConfig) -> Vec<(String, String)> {
+    // SAFETY: This is only called from the main thread before the UI threads are started.
+    let all_users = unsafe { uzers::all_users() };
+
+    let mut users: Vec<(libc::uid_t, String, String)> = all_users
+        .filter(|user| (config.min_uid..=config.max_uid).contains(&user.uid()))
+        .filter_map(|user| {
+            let Some(username) = user.name().to_str() else {
+                warn!(
+                    "Skipping user {}, because its name is invalid UTF-8",
+                    user.uid()
+                );
+                return None;
+            };
+
+            if config.hidden_users.iter().any(|hidden| hidden == username) {
+                return None;
+            }
+
+            if !is_login_shell(user.shell()) {
+                return None;
+            }
+
+            let title = if config.show_real_names {
+                get_real_name(user.uid()).unwrap_or_else(|| username.to_string())
+            } else {
+                username.to_string()
+            };
+
+            Some((user.uid(), title, username.to_string()))
+        })
+        .collect();
+
+    users.sort_by_key(|(uid, _, _)| *uid);
+    users.dedup_by(|(_, _, a), (_, _, b)| a == b);
+
+    info!("Found {} users for the user picker", users.len());
+
+    users
+        .into_iter()
+        .map(|(_, title, username)| (title, username))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_shells() {
+        assert!(is_login_shell(Path::new("/bin/bash")));
+        assert!(is_login_shell(Path::new("")));
+        assert!(!is_login_shell(Path::new("/usr/sbin/nologin")));
+        assert!(!is_login_shell(Path::new("/bin/false")));
+    }
+}